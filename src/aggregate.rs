@@ -0,0 +1,431 @@
+//! Builds `team_details` from the raw responses.
+//!
+//! Nothing here mutates the stored aggregates incrementally: a team's row is always recomputed
+//! from every match and pit response on file, so corrected, deleted or duplicated responses
+//! can't leave it permanently wrong. Robots watched by more than one scout in the same match
+//! are merged by [`consensus`](crate::consensus) first, so they only count once.
+//!
+//! Wins depend on every robot in a match, since the final score is whichever one most scouts
+//! reported, so a response for one team can change another team's wins until it's rebuilt.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use sqlx::{query, query_as, query_scalar, SqliteConnection};
+
+use crate::{
+    consensus,
+    db::{MatchResponse, PitResponse, TeamDetails, PIT_RESPONSE_COLUMNS},
+    game::FormIngest,
+    ranking,
+    season::{AggregateOp, FieldValues, Game},
+};
+
+/// Folds a team's responses into its `team_details` row.
+///
+/// `pits` should be ordered oldest first, since the latest pit response decides what the
+/// team says it can do.
+pub fn aggregate(
+    team: i32,
+    matches: &[FormIngest],
+    pits: &[PitResponse],
+    game: &dyn Game,
+) -> TeamDetails {
+    let mut details = TeamDetails::empty(team);
+    details.matches = matches.len() as i32;
+
+    for spec in game.aggregates() {
+        let values = matches.iter().filter_map(|m| m.value(spec.field));
+        let folded = match spec.op {
+            AggregateOp::Sum => Some(values.sum::<i64>()),
+            AggregateOp::Max => values.max(),
+            AggregateOp::Any => Some(values.fold(0, |any, v| any | (v != 0) as i64)),
+        };
+
+        if let (Some(column), Some(folded)) = (details.column_mut(spec.column), folded) {
+            *column = folded as i32;
+        }
+    }
+
+    details.score_accum = matches.iter().map(|m| game.score(m)).sum::<i64>() as i32;
+    details.taxi = details.taxi_true;
+    details.auto_shoot = details.auto_shoot_true;
+
+    if let Some(pit) = pits.last() {
+        details.stated_climb = pit.climb as i32;
+        details.auto_shoot |= (pit.can_shoot_auto_lower | pit.can_shoot_auto_upper) as i32;
+    }
+
+    details
+}
+
+async fn fetch_responses(
+    conn: &mut SqliteConnection,
+    team: i32,
+) -> Result<(Vec<MatchResponse>, Vec<PitResponse>), sqlx::Error> {
+    let matches = query_as::<_, MatchResponse>(
+        "SELECT * FROM match_responses WHERE team_number = ? ORDER BY match_number, timestamp",
+    )
+    .bind(team)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let matches = consensus::canonical(matches);
+
+    let pits = query_as::<_, PitResponse>(&format!(
+        "SELECT {} FROM pit_responses WHERE team = ? ORDER BY timestamp",
+        PIT_RESPONSE_COLUMNS
+    ))
+    .bind(team)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok((matches, pits))
+}
+
+/// Recomputes a team's details without storing them.
+/// Returns `None` if the team has no responses at all.
+pub async fn recompute_team(
+    conn: &mut SqliteConnection,
+    team: i32,
+    game: &dyn Game,
+) -> Result<Option<TeamDetails>, sqlx::Error> {
+    let (matches, pits) = fetch_responses(conn, team).await?;
+
+    if matches.is_empty() && pits.is_empty() {
+        return Ok(None);
+    }
+
+    let won = matches_won(conn, team, &matches, game).await?;
+    let matches: Vec<FormIngest> = matches.into_iter().map(FormIngest::from).collect();
+
+    let mut details = aggregate(team, &matches, &pits, game);
+    details.matches_won = won;
+    Ok(Some(details))
+}
+
+/// How many of the team's scouted matches its alliance won, going by the same
+/// [`ranking::outcomes`] its ranking points come from.
+async fn matches_won(
+    conn: &mut SqliteConnection,
+    team: i32,
+    played: &[MatchResponse],
+    game: &dyn Game,
+) -> Result<i32, sqlx::Error> {
+    let mut events: BTreeMap<&str, Vec<i16>> = BTreeMap::new();
+    for response in played {
+        events
+            .entry(&response.event)
+            .or_default()
+            .push(response.match_number);
+    }
+
+    let mut won = 0;
+    for (event, match_numbers) in events {
+        won += ranking::event_outcomes(conn, event, game)
+            .await?
+            .iter()
+            .filter(|o| match_numbers.contains(&o.match_number))
+            .filter(|o| o.teams.contains(&(team as u32)) && o.score > o.opponent_score)
+            .count() as i32;
+    }
+
+    Ok(won)
+}
+
+/// Recomputes and stores a team's details, removing the row if no responses are left.
+pub async fn rebuild_team(
+    conn: &mut SqliteConnection,
+    team: i32,
+    game: &dyn Game,
+) -> Result<(), sqlx::Error> {
+    match recompute_team(conn, team, game).await? {
+        Some(details) => {
+            query(r#"INSERT OR REPLACE INTO team_details VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)"#)
+                .bind(details.team)
+                .bind(details.matches)
+                .bind(details.taxi)
+                .bind(details.taxi_true)
+                .bind(details.preload)
+                .bind(details.auto_shoot)
+                .bind(details.auto_shoot_true)
+                .bind(details.auto_upper_accum)
+                .bind(details.auto_lower_accum)
+                .bind(details.shots_accum)
+                .bind(details.shots_upper_accum)
+                .bind(details.shots_lower_accum)
+                .bind(details.climb)
+                .bind(details.stated_climb)
+                .bind(details.score_accum)
+                .bind(details.matches_won)
+                .execute(&mut *conn)
+                .await?;
+        }
+        None => {
+            query("DELETE FROM team_details WHERE team = ?")
+                .bind(team)
+                .execute(&mut *conn)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Teams whose details are affected by a rebuild.
+///
+/// `team_details` isn't split by event, so scoping to an event picks the teams that played
+/// there; their rows are still built from every response they have.
+async fn teams_in_scope(
+    conn: &mut SqliteConnection,
+    event: Option<&str>,
+) -> Result<Vec<i32>, sqlx::Error> {
+    match event {
+        Some(event) => query_scalar(
+            "SELECT DISTINCT team_number FROM match_responses WHERE event = ? ORDER BY team_number",
+        )
+        .bind(event)
+        .fetch_all(conn)
+        .await,
+        None => {
+            query_scalar(
+                r#"SELECT team_number FROM match_responses
+                    UNION SELECT team FROM pit_responses
+                    UNION SELECT team FROM team_details
+                    ORDER BY 1"#,
+            )
+            .fetch_all(conn)
+            .await
+        }
+    }
+}
+
+/// Rebuilds every team's details, or just those of teams that played at `event`.
+/// Returns how many teams were rebuilt.
+pub async fn rebuild(
+    conn: &mut SqliteConnection,
+    event: Option<&str>,
+    game: &dyn Game,
+) -> Result<usize, sqlx::Error> {
+    let teams = teams_in_scope(conn, event).await?;
+
+    for &team in &teams {
+        rebuild_team(conn, team, game).await?;
+    }
+
+    Ok(teams.len())
+}
+
+/// A stored `team_details` value that doesn't match what the responses add up to.
+/// `None` means the row is missing on that side.
+#[derive(Debug, Serialize)]
+pub struct Drift {
+    pub team: i32,
+    pub column: &'static str,
+    pub stored: Option<i32>,
+    pub recomputed: Option<i32>,
+}
+
+/// Compares stored details against freshly recomputed ones without changing anything.
+pub async fn check(
+    conn: &mut SqliteConnection,
+    event: Option<&str>,
+    game: &dyn Game,
+) -> Result<Vec<Drift>, sqlx::Error> {
+    let mut drift = Vec::new();
+
+    for team in teams_in_scope(conn, event).await? {
+        let stored = query_as::<_, TeamDetails>("SELECT * FROM team_details WHERE team = ?")
+            .bind(team)
+            .fetch_optional(&mut *conn)
+            .await?;
+        let recomputed = recompute_team(conn, team, game).await?;
+
+        let stored = stored.map(|d| d.columns());
+        let recomputed = recomputed.map(|d| d.columns());
+
+        for i in 0..TeamDetails::empty(team).columns().len() {
+            let s = stored.map(|c| c[i]);
+            let r = recomputed.map(|c| c[i]);
+
+            if s.map(|(_, v)| v) != r.map(|(_, v)| v) {
+                let (column, _) = s.or(r).unwrap();
+                drift.push(Drift {
+                    team,
+                    column,
+                    stored: s.map(|(_, v)| v),
+                    recomputed: r.map(|(_, v)| v),
+                });
+            }
+        }
+    }
+
+    Ok(drift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        game::{Alliance, DetailedTeam},
+        season,
+        testing::{insert_match, match_response, with_database},
+    };
+
+    async fn stored(conn: &mut SqliteConnection, team: i32) -> Option<TeamDetails> {
+        query_as("SELECT * FROM team_details WHERE team = ?")
+            .bind(team)
+            .fetch_optional(conn)
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn rebuild_follows_added_corrected_and_deleted_responses() {
+        with_database(|mut conn| async move {
+            let game = season::current();
+
+            let mut first = match_response(1, 254);
+            first.did_taxi = true;
+            first.auto_scored_upper = 2;
+            first.auto_shots = 2;
+            first.teleop_shots = 10;
+            first.teleop_scored_upper = 6;
+            first.climb = 2;
+            insert_match(&mut conn, &first).await;
+
+            let mut second = match_response(2, 254);
+            second.teleop_shots = 8;
+            second.teleop_scored_upper = 5;
+            second.teleop_scored_lower = 1;
+            second.climb = 1;
+            let second = insert_match(&mut conn, &second).await;
+
+            let mut third = match_response(3, 254);
+            third.auto_scored_upper = 1;
+            third.auto_shots = 1;
+            let third = insert_match(&mut conn, &third).await;
+
+            rebuild_team(&mut conn, 254, game).await.unwrap();
+            let expected = TeamDetails {
+                matches: 3,
+                taxi: 1,
+                taxi_true: 1,
+                auto_shoot: 1,
+                auto_shoot_true: 1,
+                auto_upper_accum: 3,
+                shots_accum: 18,
+                shots_upper_accum: 11,
+                shots_lower_accum: 1,
+                climb: 2,
+                // 32 + 17 + 4
+                score_accum: 53,
+                ..TeamDetails::empty(254)
+            };
+            let details = stored(&mut conn, 254).await.unwrap();
+            assert_eq!(details.columns(), expected.columns());
+
+            // a scout corrects an overcount, and a response for the wrong team is dropped
+            query("UPDATE match_responses SET teleop_scored_upper = 3 WHERE id = ?")
+                .bind(second)
+                .execute(&mut conn)
+                .await
+                .unwrap();
+            query("DELETE FROM match_responses WHERE id = ?")
+                .bind(third)
+                .execute(&mut conn)
+                .await
+                .unwrap();
+
+            rebuild_team(&mut conn, 254, game).await.unwrap();
+            let expected = TeamDetails {
+                matches: 2,
+                auto_upper_accum: 2,
+                shots_upper_accum: 9,
+                // 32 + 13
+                score_accum: 45,
+                ..expected
+            };
+            let details = stored(&mut conn, 254).await.unwrap();
+            assert_eq!(details.columns(), expected.columns());
+
+            assert!(check(&mut conn, None, game).await.unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn rebuild_counts_wins_from_the_reported_scores() {
+        with_database(|mut conn| async move {
+            let game = season::current();
+            let scores = [
+                (1, Alliance::Red, 60, 40),
+                (2, Alliance::Blue, 60, 40),
+                (3, Alliance::Blue, 35, 50),
+            ];
+            for (match_number, alliance, red_score, blue_score) in scores {
+                let mut response = match_response(match_number, 254);
+                response.alliance = Some(alliance);
+                response.red_score = red_score;
+                response.blue_score = blue_score;
+                insert_match(&mut conn, &response).await;
+            }
+
+            // the other scout in match 2 saw blue win, but the report is outvoted
+            let mut outvoted = match_response(2, 1678);
+            outvoted.alliance = Some(Alliance::Blue);
+            outvoted.red_score = 40;
+            outvoted.blue_score = 60;
+            insert_match(&mut conn, &outvoted).await;
+            let mut agreed = outvoted.clone();
+            agreed.team_number = 971;
+            agreed.red_score = 60;
+            agreed.blue_score = 40;
+            insert_match(&mut conn, &agreed).await;
+
+            rebuild_team(&mut conn, 254, game).await.unwrap();
+            let details = stored(&mut conn, 254).await.unwrap();
+            assert_eq!((details.matches, details.matches_won), (3, 2));
+            assert_eq!(DetailedTeam::from(details).matches_won, 2);
+        });
+    }
+
+    #[test]
+    fn rebuild_removes_teams_without_responses() {
+        with_database(|mut conn| async move {
+            let game = season::current();
+            let id = insert_match(&mut conn, &match_response(1, 1678)).await;
+            rebuild_team(&mut conn, 1678, game).await.unwrap();
+            assert!(stored(&mut conn, 1678).await.is_some());
+
+            query("DELETE FROM match_responses WHERE id = ?")
+                .bind(id)
+                .execute(&mut conn)
+                .await
+                .unwrap();
+            rebuild_team(&mut conn, 1678, game).await.unwrap();
+
+            assert!(stored(&mut conn, 1678).await.is_none());
+            assert!(check(&mut conn, None, game).await.unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn check_reports_stored_values_that_drifted() {
+        with_database(|mut conn| async move {
+            let game = season::current();
+            insert_match(&mut conn, &match_response(1, 254)).await;
+            insert_match(&mut conn, &match_response(1, 1678)).await;
+            rebuild(&mut conn, None, game).await.unwrap();
+
+            query("UPDATE team_details SET matches = 5 WHERE team = 254")
+                .execute(&mut conn)
+                .await
+                .unwrap();
+
+            let drift = check(&mut conn, None, game).await.unwrap();
+            assert_eq!(drift.len(), 1);
+            assert_eq!((drift[0].team, drift[0].column), (254, "matches"));
+            assert_eq!((drift[0].stored, drift[0].recomputed), (Some(5), Some(1)));
+        });
+    }
+}
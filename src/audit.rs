@@ -0,0 +1,69 @@
+//! Who changed which response, and what it looked like before and after.
+
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use sqlx::{query, SqliteConnection};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, Serialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Action {
+    Update,
+    Delete,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub timestamp: i64,
+    pub changed_by: String,
+    pub action: Action,
+    pub table_name: String,
+    pub row_id: i64,
+    /// JSON snapshot of the row before the change.
+    #[serde(serialize_with = "as_json")]
+    pub before: Option<String>,
+    /// JSON snapshot of the row after the change; empty for deletes.
+    #[serde(serialize_with = "as_json")]
+    pub after: Option<String>,
+}
+
+/// Snapshots are stored as JSON text; send them back as JSON rather than as strings.
+fn as_json<S: Serializer>(snapshot: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    snapshot
+        .as_deref()
+        .and_then(|s| serde_json::from_str::<Value>(s).ok())
+        .serialize(serializer)
+}
+
+pub async fn record(
+    conn: &mut SqliteConnection,
+    changed_by: &str,
+    action: Action,
+    table_name: &str,
+    row_id: i64,
+    before: Option<&Value>,
+    after: Option<&Value>,
+) -> Result<(), sqlx::Error> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+
+    query(
+        r#"INSERT INTO audit_log (timestamp, changed_by, action, table_name, row_id, before, after)
+            VALUES (?, ?, ?, ?, ?, ?, ?)"#,
+    )
+    .bind(now)
+    .bind(changed_by)
+    .bind(action)
+    .bind(table_name)
+    .bind(row_id)
+    .bind(before.map(Value::to_string))
+    .bind(after.map(Value::to_string))
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,394 @@
+//! Server settings, layered from a TOML file, then environment variables, then command line
+//! flags. Each layer overrides the one before it, and anything left unset keeps its default.
+
+use std::{env, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    season::{self, Game, SEASONS},
+    validation::Rules,
+};
+
+/// File read when neither `--config` nor `SPECIALSCOUT_CONFIG` names one. It is fine for it
+/// not to exist.
+pub const DEFAULT_CONFIG_FILE: &str = "specialscout.toml";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address the HTTP server listens on.
+    pub bind: String,
+    /// Path to the SQLite database file.
+    pub database: String,
+    pub pool_size: u32,
+    /// An `env_logger` filter, e.g. `info` or `warn,actix_web=info`.
+    pub log_level: String,
+    /// Event used when a request doesn't name one.
+    pub event: Option<String>,
+    /// Year of the game to score. The newest known season when unset.
+    pub season: Option<u16>,
+    /// Bearer token required by admin routes. They are refused when unset, unless
+    /// `open_admin` is set. Registering scouts is an admin route, so without either no
+    /// scout can get a token to submit responses with.
+    pub admin_token: Option<String>,
+    /// Lets anyone use admin routes while no `admin_token` is set. Only for trusted networks.
+    pub open_admin: bool,
+    /// Directory uploaded team photos are kept in.
+    pub image_dir: String,
+    /// Limits submitted forms are checked against. Only settable from the file.
+    pub validation: Rules,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind: "0.0.0.0:80".to_string(),
+            database: "db.sqlite".to_string(),
+            pool_size: 10,
+            log_level: "info,sqlx=warn".to_string(),
+            event: None,
+            season: None,
+            admin_token: None,
+            open_admin: false,
+            image_dir: "images".to_string(),
+            validation: Rules::default(),
+        }
+    }
+}
+
+/// What the command line asked the server to do.
+pub enum Command {
+    Serve(Config),
+    /// Print the effective settings and exit.
+    PrintConfig(Config),
+    Help,
+}
+
+pub const USAGE: &str = "Usage: specialscout-db [OPTIONS]
+
+Options:
+    --config <FILE>         TOML settings file [env: SPECIALSCOUT_CONFIG, default: specialscout.toml]
+    --bind <ADDR>           Address to listen on [env: SPECIALSCOUT_BIND]
+    --database <FILE>       SQLite database file [env: SPECIALSCOUT_DATABASE]
+    --pool-size <N>         Database connections to keep open [env: SPECIALSCOUT_POOL_SIZE]
+    --log-level <FILTER>    Log filter, e.g. info or debug [env: SPECIALSCOUT_LOG_LEVEL]
+    --event <KEY>           Active event key [env: SPECIALSCOUT_EVENT]
+    --season <YEAR>         Game to score, e.g. 2022 [env: SPECIALSCOUT_SEASON]
+    --admin-token <TOKEN>   Token required by admin routes [env: SPECIALSCOUT_ADMIN_TOKEN]
+    --open-admin <BOOL>     Allow admin routes without a token [env: SPECIALSCOUT_OPEN_ADMIN]
+    --image-dir <DIR>       Where team photos are stored [env: SPECIALSCOUT_IMAGE_DIR]
+    --print-config          Print the effective settings and exit
+    --help                  Print this message and exit";
+
+/// Works out the settings from the process's environment and `args`, which should not
+/// include the program name.
+pub fn load(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let flags = parse_flags(args)?;
+    if flags.help {
+        return Ok(Command::Help);
+    }
+
+    let file = flags
+        .get("config")
+        .map(str::to_string)
+        .or_else(|| env::var("SPECIALSCOUT_CONFIG").ok());
+
+    let config = match &file {
+        Some(path) => read_file(Path::new(path))?,
+        None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+            read_file(Path::new(DEFAULT_CONFIG_FILE))?
+        }
+        None => Config::default(),
+    };
+
+    let config = layer(config, |name| env::var(name).ok(), &flags)?;
+
+    if flags.print_config {
+        Ok(Command::PrintConfig(config))
+    } else {
+        Ok(Command::Serve(config))
+    }
+}
+
+/// Applies `SPECIALSCOUT_*` variables from `env` and then `flags` over settings from a file.
+fn layer(
+    mut config: Config,
+    env: impl Fn(&str) -> Option<String>,
+    flags: &Flags,
+) -> Result<Config, String> {
+    config.apply(|key| env(&format!("SPECIALSCOUT_{}", key.to_uppercase())))?;
+    config.apply(|key| flags.get(key).map(str::to_string))?;
+    Ok(config)
+}
+
+fn read_file(path: &Path) -> Result<Config, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    toml::from_str(&text).map_err(|e| format!("invalid {}: {}", path.display(), e))
+}
+
+impl Config {
+    /// Overrides each setting `lookup` has a value for. Keys are the TOML field names.
+    fn apply(&mut self, lookup: impl Fn(&str) -> Option<String>) -> Result<(), String> {
+        if let Some(bind) = lookup("bind") {
+            self.bind = bind;
+        }
+        if let Some(database) = lookup("database") {
+            self.database = database;
+        }
+        if let Some(pool_size) = lookup("pool_size") {
+            self.pool_size = pool_size
+                .parse()
+                .map_err(|_| format!("pool_size must be a whole number, got {:?}", pool_size))?;
+        }
+        if let Some(log_level) = lookup("log_level") {
+            self.log_level = log_level;
+        }
+        if let Some(event) = lookup("event") {
+            self.event = Some(event).filter(|e| !e.is_empty());
+        }
+        if let Some(season) = lookup("season") {
+            self.season = match season.as_str() {
+                "" => None,
+                year => Some(
+                    year.parse()
+                        .map_err(|_| format!("season must be a year, got {:?}", season))?,
+                ),
+            };
+        }
+        if let Some(admin_token) = lookup("admin_token") {
+            self.admin_token = Some(admin_token).filter(|t| !t.is_empty());
+        }
+        if let Some(open_admin) = lookup("open_admin") {
+            self.open_admin = open_admin
+                .parse()
+                .map_err(|_| format!("open_admin must be true or false, got {:?}", open_admin))?;
+        }
+        if let Some(image_dir) = lookup("image_dir") {
+            self.image_dir = image_dir;
+        }
+
+        if self.pool_size == 0 {
+            return Err("pool_size must be at least 1".to_string());
+        }
+        if let Some(year) = self.season.filter(|&y| season::by_year(y).is_none()) {
+            let known: Vec<String> = SEASONS.iter().map(|g| g.year().to_string()).collect();
+            return Err(format!(
+                "unknown season {}, expected one of {}",
+                year,
+                known.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    /// The game to score: the configured season, or the newest one.
+    pub fn game(&self) -> &'static dyn Game {
+        self.season
+            .and_then(season::by_year)
+            .unwrap_or_else(season::current)
+    }
+
+    /// The settings as TOML, with the admin token masked so the output can be shared.
+    pub fn to_toml(&self) -> String {
+        let mut shown = self.clone();
+        if shown.admin_token.is_some() {
+            shown.admin_token = Some("********".to_string());
+        }
+        toml::to_string(&shown).expect("Config always serializes")
+    }
+}
+
+#[derive(Default)]
+struct Flags {
+    values: Vec<(String, String)>,
+    print_config: bool,
+    help: bool,
+}
+
+impl Flags {
+    /// The last value given for a flag, under its TOML field name.
+    fn get(&self, key: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+const VALUE_FLAGS: &[&str] = &[
+    "config",
+    "bind",
+    "database",
+    "pool-size",
+    "log-level",
+    "event",
+    "season",
+    "admin-token",
+    "open-admin",
+    "image-dir",
+];
+
+/// Accepts `--flag value` and `--flag=value`.
+fn parse_flags(args: impl IntoIterator<Item = String>) -> Result<Flags, String> {
+    let mut flags = Flags::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let name = arg
+            .strip_prefix("--")
+            .ok_or_else(|| format!("unexpected argument {:?}\n\n{}", arg, USAGE))?;
+        let (name, inline) = match name.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (name, None),
+        };
+
+        match name {
+            "print-config" => flags.print_config = true,
+            "help" => flags.help = true,
+            _ if VALUE_FLAGS.contains(&name) => {
+                let value = match inline.or_else(|| args.next()) {
+                    Some(value) => value,
+                    None => return Err(format!("--{} needs a value", name)),
+                };
+                flags.values.push((name.replace('-', "_"), value));
+            }
+            _ => return Err(format!("unknown option --{}\n\n{}", name, USAGE)),
+        }
+    }
+
+    Ok(flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    fn env<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        move |name| vars.get(name).map(|v| v.to_string())
+    }
+
+    #[test]
+    fn flags_take_a_separate_or_inline_value() {
+        let flags = parse_flags(args(&[
+            "--bind",
+            "127.0.0.1:8080",
+            "--pool-size=4",
+            "--event=2022mnmi",
+            "--print-config",
+            "--event",
+            "2022wila",
+        ]))
+        .unwrap();
+
+        assert_eq!(flags.get("bind"), Some("127.0.0.1:8080"));
+        assert_eq!(flags.get("pool_size"), Some("4"));
+        // the last one given wins
+        assert_eq!(flags.get("event"), Some("2022wila"));
+        assert_eq!(flags.get("admin_token"), None);
+        assert!(flags.print_config);
+        assert!(!flags.help);
+    }
+
+    #[test]
+    fn bad_flags_are_rejected() {
+        let error = |a: &[&str]| parse_flags(args(a)).err().unwrap();
+
+        assert!(error(&["db.sqlite"]).starts_with("unexpected argument \"db.sqlite\""));
+        assert!(error(&["--port", "80"]).starts_with("unknown option --port"));
+        assert_eq!(error(&["--bind"]), "--bind needs a value");
+    }
+
+    #[test]
+    fn flags_override_env_which_overrides_the_file() {
+        let file: Config = toml::from_str(
+            r#"
+            bind = "0.0.0.0:8000"
+            event = "2022file"
+            pool_size = 3
+            admin_token = "from-file"
+            "#,
+        )
+        .unwrap();
+        let env = env(&[
+            ("SPECIALSCOUT_EVENT", "2022env"),
+            ("SPECIALSCOUT_ADMIN_TOKEN", "from-env"),
+        ]);
+        let flags = parse_flags(args(&["--admin-token", "from-flag"])).unwrap();
+
+        let config = layer(file, env, &flags).unwrap();
+        assert_eq!(config.bind, "0.0.0.0:8000");
+        assert_eq!(config.pool_size, 3);
+        assert_eq!(config.event.as_deref(), Some("2022env"));
+        assert_eq!(config.admin_token.as_deref(), Some("from-flag"));
+        // untouched by every layer
+        assert_eq!(config.database, "db.sqlite");
+    }
+
+    #[test]
+    fn empty_values_clear_optional_settings() {
+        let file = Config {
+            event: Some("2022file".to_string()),
+            admin_token: Some("secret".to_string()),
+            ..Config::default()
+        };
+        let env = env(&[("SPECIALSCOUT_EVENT", ""), ("SPECIALSCOUT_ADMIN_TOKEN", "")]);
+
+        let config = layer(file, env, &Flags::default()).unwrap();
+        assert_eq!(config.event, None);
+        assert_eq!(config.admin_token, None);
+    }
+
+    #[test]
+    fn only_specialscout_variables_are_read() {
+        let env = env(&[
+            ("DATABASE_URL", "sqlite://elsewhere.db"),
+            ("SPECIALSCOUT_DATABASE", "scouting.sqlite"),
+        ]);
+
+        let config = layer(Config::default(), env, &Flags::default()).unwrap();
+        assert_eq!(config.database, "scouting.sqlite");
+    }
+
+    #[test]
+    fn bad_values_are_rejected() {
+        let error = |name: &str, value: &str| {
+            let vars = [(name, value)];
+            layer(Config::default(), env(&vars), &Flags::default())
+                .err()
+                .unwrap()
+        };
+
+        assert_eq!(
+            error("SPECIALSCOUT_POOL_SIZE", "ten"),
+            "pool_size must be a whole number, got \"ten\""
+        );
+        assert_eq!(
+            error("SPECIALSCOUT_POOL_SIZE", "0"),
+            "pool_size must be at least 1"
+        );
+        assert_eq!(
+            error("SPECIALSCOUT_OPEN_ADMIN", "yes"),
+            "open_admin must be true or false, got \"yes\""
+        );
+        assert_eq!(
+            error("SPECIALSCOUT_SEASON", "next"),
+            "season must be a year, got \"next\""
+        );
+        assert!(error("SPECIALSCOUT_SEASON", "1999").starts_with("unknown season 1999"));
+    }
+
+    #[test]
+    fn unknown_file_settings_are_rejected() {
+        assert!(toml::from_str::<Config>("port = 80").is_err());
+    }
+}
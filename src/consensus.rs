@@ -0,0 +1,203 @@
+//! Merges responses from several scouts watching the same robot in the same match.
+//!
+//! Each robot-match should count once towards a team's aggregates, however many scouts
+//! covered it. Duplicates are folded into one canonical observation: counts take the median,
+//! yes/no fields the majority, and the climb the highest level anybody saw. Ties go to the
+//! most recent response.
+
+use std::collections::BTreeMap;
+
+use crate::{db::MatchResponse, opr};
+
+/// Groups responses by event, match and team, merging each group into one.
+/// The result is ordered by event, match and team.
+pub fn canonical(responses: Vec<MatchResponse>) -> Vec<MatchResponse> {
+    let mut groups: BTreeMap<(String, i16, u32), Vec<MatchResponse>> = BTreeMap::new();
+    for response in responses {
+        groups
+            .entry((
+                response.event.clone(),
+                response.match_number,
+                response.team_number,
+            ))
+            .or_default()
+            .push(response);
+    }
+
+    groups.into_values().map(merge).collect()
+}
+
+/// Folds observations of one robot in one match into a single response. The merged
+/// response keeps the earliest id, so it can still be traced back to a stored row.
+pub fn merge(mut observed: Vec<MatchResponse>) -> MatchResponse {
+    if observed.len() == 1 {
+        return observed.pop().unwrap();
+    }
+
+    // newest first, so ties resolve in its favour
+    observed.sort_by_key(|r| std::cmp::Reverse((r.timestamp, r.id)));
+
+    let median = |field: fn(&MatchResponse) -> i16| {
+        let mut values: Vec<i16> = observed.iter().map(field).collect();
+        values.sort_unstable();
+        let mid = values.len() / 2;
+        if values.len() % 2 == 1 {
+            values[mid]
+        } else {
+            ((values[mid - 1] as i32 + values[mid] as i32) / 2) as i16
+        }
+    };
+    let majority = |field: fn(&MatchResponse) -> bool| {
+        let yes = observed.iter().filter(|r| field(r)).count();
+        match (yes * 2).cmp(&observed.len()) {
+            std::cmp::Ordering::Greater => true,
+            std::cmp::Ordering::Less => false,
+            std::cmp::Ordering::Equal => field(&observed[0]),
+        }
+    };
+    let scores = |field: fn(&MatchResponse) -> i32| {
+        let values: Vec<i32> = observed.iter().map(field).collect();
+        opr::mode(&values)
+    };
+
+    let comment = observed
+        .iter()
+        .map(|r| r.comment.trim())
+        .filter(|c| !c.is_empty())
+        .collect::<Vec<_>>()
+        .join(" | ");
+
+    let earliest = observed.iter().min_by_key(|r| r.id).unwrap();
+    let latest = &observed[0];
+
+    MatchResponse {
+        id: earliest.id,
+        timestamp: latest.timestamp,
+        uuid: latest.uuid,
+        event: latest.event.clone(),
+        team_number: latest.team_number,
+        match_number: latest.match_number,
+        did_preload: majority(|r| r.did_preload),
+        did_taxi: majority(|r| r.did_taxi),
+        got_field_cargo: majority(|r| r.got_field_cargo),
+        did_hp_shot: majority(|r| r.did_hp_shot),
+        did_hp_sink: majority(|r| r.did_hp_sink),
+        auto_scored_lower: median(|r| r.auto_scored_lower),
+        auto_scored_upper: median(|r| r.auto_scored_upper),
+        auto_shots: median(|r| r.auto_shots),
+        teleop_scored_lower: median(|r| r.teleop_scored_lower),
+        teleop_scored_upper: median(|r| r.teleop_scored_upper),
+        teleop_shots: median(|r| r.teleop_shots),
+        pins: median(|r| r.pins),
+        times_pinned: median(|r| r.times_pinned),
+        penalties: median(|r| r.penalties),
+        performance: median(|r| r.performance),
+        red_score: scores(|r| r.red_score),
+        blue_score: scores(|r| r.blue_score),
+        climb: observed.iter().map(|r| r.climb).max().unwrap(),
+        comment,
+        alliance: observed.iter().find_map(|r| r.alliance),
+        submission_id: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(id: i64, timestamp: i32) -> MatchResponse {
+        MatchResponse {
+            id,
+            timestamp,
+            uuid: id as u32,
+            event: "2022test".to_string(),
+            team_number: 254,
+            match_number: 1,
+            did_preload: false,
+            did_taxi: false,
+            got_field_cargo: false,
+            did_hp_shot: false,
+            did_hp_sink: false,
+            auto_scored_lower: 0,
+            auto_scored_upper: 0,
+            auto_shots: 0,
+            teleop_scored_lower: 0,
+            teleop_scored_upper: 0,
+            teleop_shots: 0,
+            pins: 0,
+            times_pinned: 0,
+            penalties: 0,
+            performance: 0,
+            red_score: 0,
+            blue_score: 0,
+            climb: 0,
+            comment: String::new(),
+            alliance: None,
+            submission_id: None,
+        }
+    }
+
+    #[test]
+    fn counts_take_the_median() {
+        let mut a = response(1, 100);
+        let mut b = response(2, 200);
+        let mut c = response(3, 300);
+        a.teleop_scored_upper = 2;
+        b.teleop_scored_upper = 9;
+        c.teleop_scored_upper = 4;
+
+        assert_eq!(merge(vec![a.clone(), b.clone(), c]).teleop_scored_upper, 4);
+        // an even number of observations splits the middle two, rounding down
+        assert_eq!(merge(vec![a, b]).teleop_scored_upper, 5);
+    }
+
+    #[test]
+    fn majority_tie_goes_to_newest() {
+        let mut older = response(1, 100);
+        let mut newer = response(2, 200);
+        older.did_taxi = true;
+        assert!(!merge(vec![older.clone(), newer.clone()]).did_taxi);
+
+        older.did_taxi = false;
+        newer.did_taxi = true;
+        assert!(merge(vec![newer, older]).did_taxi);
+    }
+
+    #[test]
+    fn majority_wins_over_newest() {
+        let mut a = response(1, 100);
+        let mut b = response(2, 200);
+        let c = response(3, 300);
+        a.did_preload = true;
+        b.did_preload = true;
+
+        assert!(merge(vec![a, b, c]).did_preload);
+    }
+
+    #[test]
+    fn merged_response_keeps_earliest_id_and_highest_climb() {
+        let mut a = response(7, 300);
+        let mut b = response(3, 100);
+        a.climb = 2;
+        b.climb = 3;
+        a.comment = "fast".to_string();
+        b.comment = " ".to_string();
+
+        let merged = merge(vec![a, b]);
+        assert_eq!(merged.id, 3);
+        assert_eq!(merged.timestamp, 300);
+        assert_eq!(merged.uuid, 7);
+        assert_eq!(merged.climb, 3);
+        assert_eq!(merged.comment, "fast");
+    }
+
+    #[test]
+    fn canonical_merges_each_robot_match_once() {
+        let mut other_team = response(3, 100);
+        other_team.team_number = 1678;
+
+        let merged = canonical(vec![response(1, 100), other_team, response(2, 200)]);
+        let keys: Vec<(u32, i64)> = merged.iter().map(|r| (r.team_number, r.id)).collect();
+        assert_eq!(keys, [(254, 1), (1678, 3)]);
+    }
+}
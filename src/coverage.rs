@@ -0,0 +1,306 @@
+//! Cross-checks an event's schedule against the match responses actually collected.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::{
+    game::Alliance,
+    schedule::{Assignment, ScheduledMatch},
+};
+
+/// The parts of a match response that say which robot was watched.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ScoutedSlot {
+    pub id: i64,
+    pub uuid: u32,
+    pub match_number: i16,
+    pub team_number: u32,
+}
+
+/// A scheduled robot with no response.
+#[derive(Debug, Serialize)]
+pub struct Gap {
+    pub match_number: i16,
+    pub alliance: Alliance,
+    pub station: u8,
+    pub team_number: u32,
+    /// Who was assigned to watch it, if assignments were generated.
+    pub assigned: Option<u32>,
+}
+
+/// A scheduled robot with more than one response.
+#[derive(Debug, Serialize)]
+pub struct DoubleScouted {
+    pub match_number: i16,
+    pub team_number: u32,
+    /// Response ids.
+    pub responses: Vec<i64>,
+    pub uuids: Vec<u32>,
+}
+
+/// A response for a team that wasn't in that match, or for a match that isn't scheduled.
+#[derive(Debug, Serialize)]
+pub struct Unscheduled {
+    pub id: i64,
+    pub uuid: u32,
+    pub match_number: i16,
+    pub team_number: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Coverage {
+    /// Gaps are only reported up to this match, the latest one that has been played by the
+    /// look of the responses, so matches still to come don't show up as missing.
+    pub through: Option<i16>,
+    pub scheduled: usize,
+    pub scouted: usize,
+    pub gaps: Vec<Gap>,
+    pub double_scouted: Vec<DoubleScouted>,
+    pub unscheduled: Vec<Unscheduled>,
+}
+
+/// The latest match that has been played, going by the responses.
+///
+/// With a schedule, that is the latest scheduled match with a response for one of its robots,
+/// so one mistyped match number can't make the rest of the schedule look missed. Without one,
+/// a match only counts once more than one robot has been reported in it.
+fn latest_played(schedule: &[ScheduledMatch], responses: &[ScoutedSlot]) -> Option<i16> {
+    if schedule.is_empty() {
+        let mut robots: BTreeMap<i16, HashSet<u32>> = BTreeMap::new();
+        for response in responses {
+            robots
+                .entry(response.match_number)
+                .or_default()
+                .insert(response.team_number);
+        }
+
+        return robots
+            .into_iter()
+            .rev()
+            .find(|(_, teams)| teams.len() > 1)
+            .map(|(match_number, _)| match_number);
+    }
+
+    let slots: HashSet<(i16, u32)> = schedule
+        .iter()
+        .flat_map(|m| m.stations().map(move |(_, _, team)| (m.match_number, team)))
+        .collect();
+
+    responses
+        .iter()
+        .filter(|r| slots.contains(&(r.match_number, r.team_number)))
+        .map(|r| r.match_number)
+        .max()
+}
+
+pub fn report(
+    schedule: &[ScheduledMatch],
+    assignments: &[Assignment],
+    responses: &[ScoutedSlot],
+) -> Coverage {
+    let through = latest_played(schedule, responses);
+
+    let mut by_slot: BTreeMap<(i16, u32), Vec<&ScoutedSlot>> = BTreeMap::new();
+    for response in responses {
+        by_slot
+            .entry((response.match_number, response.team_number))
+            .or_default()
+            .push(response);
+    }
+
+    let assigned: HashMap<(i16, u32), u32> = assignments
+        .iter()
+        .map(|a| ((a.match_number, a.team_number), a.uuid))
+        .collect();
+
+    let mut coverage = Coverage {
+        through,
+        scheduled: 0,
+        scouted: 0,
+        gaps: Vec::new(),
+        double_scouted: Vec::new(),
+        unscheduled: Vec::new(),
+    };
+
+    for scheduled in schedule {
+        for (alliance, station, team_number) in scheduled.stations() {
+            let slot = (scheduled.match_number, team_number);
+            coverage.scheduled += 1;
+
+            match by_slot.remove(&slot) {
+                Some(found) => {
+                    coverage.scouted += 1;
+                    if found.len() > 1 {
+                        coverage.double_scouted.push(DoubleScouted {
+                            match_number: scheduled.match_number,
+                            team_number,
+                            responses: found.iter().map(|r| r.id).collect(),
+                            uuids: found.iter().map(|r| r.uuid).collect(),
+                        });
+                    }
+                }
+                None if Some(scheduled.match_number) <= through => coverage.gaps.push(Gap {
+                    match_number: scheduled.match_number,
+                    alliance,
+                    station,
+                    team_number,
+                    assigned: assigned.get(&slot).copied(),
+                }),
+                None => {}
+            }
+        }
+    }
+
+    // whatever is left didn't line up with any scheduled robot
+    coverage.unscheduled = by_slot
+        .into_values()
+        .flatten()
+        .map(|r| Unscheduled {
+            id: r.id,
+            uuid: r.uuid,
+            match_number: r.match_number,
+            team_number: r.team_number,
+        })
+        .collect();
+
+    coverage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduled(match_number: i16, red: [u32; 3], blue: [u32; 3]) -> ScheduledMatch {
+        ScheduledMatch {
+            match_number,
+            red: red.to_vec(),
+            blue: blue.to_vec(),
+        }
+    }
+
+    fn schedule() -> Vec<ScheduledMatch> {
+        vec![
+            scheduled(1, [1, 2, 3], [4, 5, 6]),
+            scheduled(2, [7, 8, 9], [1, 2, 3]),
+            scheduled(3, [4, 5, 6], [7, 8, 9]),
+        ]
+    }
+
+    fn slot(id: i64, match_number: i16, team_number: u32) -> ScoutedSlot {
+        ScoutedSlot {
+            id,
+            uuid: id as u32,
+            match_number,
+            team_number,
+        }
+    }
+
+    /// Responses for every robot in the first `matches` matches of [`schedule`].
+    fn everyone(matches: usize) -> Vec<ScoutedSlot> {
+        schedule()
+            .iter()
+            .take(matches)
+            .flat_map(|m| {
+                m.stations()
+                    .map(|(_, _, team)| (m.match_number, team))
+                    .collect::<Vec<_>>()
+            })
+            .enumerate()
+            .map(|(i, (match_number, team))| slot(i as i64 + 1, match_number, team))
+            .collect()
+    }
+
+    #[test]
+    fn gaps_stop_at_the_latest_match_played() {
+        let mut responses = everyone(2);
+        let missed = responses.remove(7);
+
+        let coverage = report(&schedule(), &[], &responses);
+        assert_eq!(coverage.through, Some(2));
+        assert_eq!((coverage.scheduled, coverage.scouted), (18, 11));
+        assert_eq!(coverage.gaps.len(), 1);
+        let gap = &coverage.gaps[0];
+        assert_eq!((gap.match_number, gap.team_number), (2, missed.team_number));
+        assert_eq!((gap.alliance, gap.station), (Alliance::Red, 2));
+        assert!(coverage.double_scouted.is_empty());
+        assert!(coverage.unscheduled.is_empty());
+    }
+
+    #[test]
+    fn gaps_name_who_was_assigned() {
+        let assignments = [Assignment {
+            match_number: 1,
+            uuid: 42,
+            alliance: Alliance::Blue,
+            station: 3,
+            team_number: 6,
+        }];
+        let responses = vec![slot(1, 1, 1)];
+
+        let coverage = report(&schedule(), &assignments, &responses);
+        let gap = coverage.gaps.iter().find(|g| g.team_number == 6).unwrap();
+        assert_eq!(gap.assigned, Some(42));
+        assert_eq!(
+            coverage
+                .gaps
+                .iter()
+                .filter(|g| g.assigned.is_none())
+                .count(),
+            4
+        );
+    }
+
+    #[test]
+    fn mistyped_match_number_is_not_played() {
+        let mut responses = everyone(1);
+        // match 30 isn't scheduled, and team 1 isn't in match 3
+        responses.push(slot(100, 30, 1));
+        responses.push(slot(101, 3, 1));
+
+        let coverage = report(&schedule(), &[], &responses);
+        assert_eq!(coverage.through, Some(1));
+        assert!(coverage.gaps.is_empty());
+        let ids: Vec<i64> = coverage.unscheduled.iter().map(|u| u.id).collect();
+        assert_eq!(ids, [101, 100]);
+    }
+
+    #[test]
+    fn robots_watched_twice_are_reported() {
+        let mut responses = everyone(1);
+        responses.push(slot(50, 1, 4));
+
+        let coverage = report(&schedule(), &[], &responses);
+        assert_eq!(coverage.scouted, 6);
+        assert_eq!(coverage.double_scouted.len(), 1);
+        let double = &coverage.double_scouted[0];
+        assert_eq!((double.match_number, double.team_number), (1, 4));
+        assert_eq!(double.responses, [4, 50]);
+        assert_eq!(double.uuids, [4, 50]);
+    }
+
+    #[test]
+    fn nothing_scouted_means_nothing_missed() {
+        let coverage = report(&schedule(), &[], &[]);
+        assert_eq!(coverage.through, None);
+        assert_eq!((coverage.scheduled, coverage.scouted), (18, 0));
+        assert!(coverage.gaps.is_empty());
+    }
+
+    #[test]
+    fn without_a_schedule_lone_reports_are_outliers() {
+        let responses = [
+            slot(1, 1, 1),
+            slot(2, 1, 2),
+            slot(3, 2, 3),
+            slot(4, 2, 4),
+            slot(5, 90, 5),
+        ];
+        assert_eq!(latest_played(&[], &responses), Some(2));
+        assert_eq!(latest_played(&[], &responses[4..]), None);
+
+        let coverage = report(&[], &[], &responses);
+        assert_eq!(coverage.through, Some(2));
+        assert_eq!(coverage.unscheduled.len(), 5);
+    }
+}
@@ -0,0 +1,255 @@
+//! Rows as they are stored in the database.
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::{Alliance, DetailedTeam, FormIngest, Size, Team};
+
+#[derive(sqlx::FromRow, Clone)]
+pub struct TeamDetails {
+    pub team: i32,
+    pub matches: i32,
+    pub taxi: i32,
+    pub taxi_true: i32,
+    pub preload: i32,
+    pub auto_shoot: i32,
+    pub auto_shoot_true: i32,
+    pub auto_upper_accum: i32,
+    pub auto_lower_accum: i32,
+    pub shots_accum: i32,
+    pub shots_upper_accum: i32,
+    pub shots_lower_accum: i32,
+    pub climb: i32,
+    pub stated_climb: i32,
+    pub score_accum: i32,
+    pub matches_won: i32,
+}
+
+impl TeamDetails {
+    /// A team with nothing recorded. Climbs start at -1, the same as "no attempt" on the forms.
+    pub fn empty(team: i32) -> Self {
+        TeamDetails {
+            team,
+            matches: 0,
+            taxi: 0,
+            taxi_true: 0,
+            preload: 0,
+            auto_shoot: 0,
+            auto_shoot_true: 0,
+            auto_upper_accum: 0,
+            auto_lower_accum: 0,
+            shots_accum: 0,
+            shots_upper_accum: 0,
+            shots_lower_accum: 0,
+            climb: -1,
+            stated_climb: -1,
+            score_accum: 0,
+            matches_won: 0,
+        }
+    }
+
+    /// Every column by name, in table order.
+    pub fn columns(&self) -> [(&'static str, i32); 16] {
+        [
+            ("team", self.team),
+            ("matches", self.matches),
+            ("taxi", self.taxi),
+            ("taxi_true", self.taxi_true),
+            ("preload", self.preload),
+            ("auto_shoot", self.auto_shoot),
+            ("auto_shoot_true", self.auto_shoot_true),
+            ("auto_upper_accum", self.auto_upper_accum),
+            ("auto_lower_accum", self.auto_lower_accum),
+            ("shots_accum", self.shots_accum),
+            ("shots_upper_accum", self.shots_upper_accum),
+            ("shots_lower_accum", self.shots_lower_accum),
+            ("climb", self.climb),
+            ("stated_climb", self.stated_climb),
+            ("score_accum", self.score_accum),
+            ("matches_won", self.matches_won),
+        ]
+    }
+
+    /// Mutable access to a column by name, for columns driven by a season's aggregates.
+    pub fn column_mut(&mut self, column: &str) -> Option<&mut i32> {
+        Some(match column {
+            "matches" => &mut self.matches,
+            "taxi" => &mut self.taxi,
+            "taxi_true" => &mut self.taxi_true,
+            "preload" => &mut self.preload,
+            "auto_shoot" => &mut self.auto_shoot,
+            "auto_shoot_true" => &mut self.auto_shoot_true,
+            "auto_upper_accum" => &mut self.auto_upper_accum,
+            "auto_lower_accum" => &mut self.auto_lower_accum,
+            "shots_accum" => &mut self.shots_accum,
+            "shots_upper_accum" => &mut self.shots_upper_accum,
+            "shots_lower_accum" => &mut self.shots_lower_accum,
+            "climb" => &mut self.climb,
+            "stated_climb" => &mut self.stated_climb,
+            "score_accum" => &mut self.score_accum,
+            _ => return None,
+        })
+    }
+}
+
+/// Divides an accumulator by a count, treating an empty count as 0.
+fn per(accum: i32, count: i32) -> f32 {
+    if count == 0 {
+        0.0
+    } else {
+        accum as f32 / count as f32
+    }
+}
+
+impl From<TeamDetails> for DetailedTeam {
+    fn from(details: TeamDetails) -> Self {
+        DetailedTeam {
+            team: Team::from(details.team as u16),
+            matches_played: details.matches as u16,
+            matches_won: details.matches_won as u16,
+            balls_thrown: details.shots_accum as u16,
+            balls_sunk_lower: details.shots_lower_accum as u16,
+            balls_sunk_upper: details.shots_upper_accum as u16,
+            def: 0.0,
+            driv: 0.0,
+            conf: 0.0,
+            avg_score: per(details.score_accum, details.matches),
+            avg_auto_upper: per(details.auto_upper_accum, details.matches),
+            avg_auto_lower: per(details.auto_lower_accum, details.matches),
+            teleop_accuracy: per(details.shots_upper_accum, details.shots_accum),
+            taxi: details.taxi_true != 0,
+            preload: details.preload != 0,
+            auto_shoot: details.auto_shoot_true != 0,
+            stated_auto_shoot: details.auto_shoot != 0,
+            climb: details.climb as i8,
+            stated_climb: details.stated_climb as i8,
+            rp: 0,
+            prev_points: 0,
+            est_points: per(details.score_accum, details.matches).round() as i16,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Serialize, Deserialize, Clone)]
+pub struct MatchResponse {
+    pub id: i64,
+    pub timestamp: i32,
+    pub uuid: u32,
+    pub event: String,
+    pub team_number: u32,
+    pub match_number: i16,
+    pub did_preload: bool,
+    pub did_taxi: bool,
+    pub got_field_cargo: bool,
+    pub did_hp_shot: bool,
+    pub did_hp_sink: bool,
+    pub auto_scored_lower: i16,
+    pub auto_scored_upper: i16,
+    pub auto_shots: i16,
+    pub teleop_scored_lower: i16,
+    pub teleop_scored_upper: i16,
+    pub teleop_shots: i16,
+    pub pins: i16,
+    pub times_pinned: i16,
+    pub penalties: i16,
+    pub performance: i16,
+    pub red_score: i32,
+    pub blue_score: i32,
+    pub climb: i8,
+    pub comment: String,
+    pub alliance: Option<Alliance>,
+    pub submission_id: Option<String>,
+}
+
+/// Every `pit_responses` column except the picture, which is only ever served on its own.
+///
+/// Sizes are stored in INTEGER columns, so whole numbers come back as integers without the cast.
+pub const PIT_RESPONSE_COLUMNS: &str = r#"id, timestamp, uuid, team, team_name, drivetrain, weight,
+    CAST(size_x AS REAL) AS size_x, CAST(size_y AS REAL) AS size_y, CAST(size_z AS REAL) AS size_z,
+    can_shoot_auto_upper, can_shoot_auto_lower, can_shoot_teleop_upper, can_shoot_teleop_lower,
+    climb, build_quality, confidence, driver_team, comment, image_ids"#;
+
+#[derive(sqlx::FromRow, Serialize, Deserialize, Clone)]
+pub struct PitResponse {
+    pub id: i64,
+    pub timestamp: i32,
+    pub uuid: u32,
+    pub team: i32,
+    pub team_name: String,
+    pub drivetrain: String,
+    pub weight: u16,
+    pub size_x: f32,
+    pub size_y: f32,
+    pub size_z: f32,
+    pub can_shoot_auto_upper: bool,
+    pub can_shoot_auto_lower: bool,
+    pub can_shoot_teleop_upper: bool,
+    pub can_shoot_teleop_lower: bool,
+    pub climb: i8,
+    pub build_quality: i16,
+    pub confidence: i16,
+    pub driver_team: i16,
+    pub comment: String,
+    /// JSON list of photo ids in the image store.
+    pub image_ids: String,
+}
+
+impl From<MatchResponse> for FormIngest {
+    fn from(resp: MatchResponse) -> Self {
+        FormIngest::Match {
+            timestamp: resp.timestamp,
+            event: resp.event,
+            match_number: resp.match_number,
+            team_number: resp.team_number,
+            did_preload: resp.did_preload,
+            did_taxi: resp.did_taxi,
+            got_field_cargo: resp.got_field_cargo,
+            did_hp_shot: resp.did_hp_shot,
+            did_hp_sink: resp.did_hp_sink,
+            auto_scored_lower: resp.auto_scored_lower,
+            auto_scored_upper: resp.auto_scored_upper,
+            auto_shots: resp.auto_shots,
+            teleop_scored_lower: resp.teleop_scored_lower,
+            teleop_scored_upper: resp.teleop_scored_upper,
+            teleop_shots: resp.teleop_shots,
+            pins: resp.pins,
+            times_pinned: resp.times_pinned,
+            penalties: resp.penalties,
+            climb: resp.climb,
+            performance: resp.performance,
+            comments: resp.comment,
+            red_score: resp.red_score,
+            blue_score: resp.blue_score,
+            alliance: resp.alliance,
+            submission_id: resp.submission_id,
+        }
+    }
+}
+
+impl From<PitResponse> for FormIngest {
+    fn from(resp: PitResponse) -> Self {
+        FormIngest::Pit {
+            time_stamp: resp.timestamp,
+            team_name: resp.team_name,
+            team_number: resp.team,
+            drivetrain: resp.drivetrain,
+            weight: resp.weight,
+            size: Size {
+                x: resp.size_x,
+                y: resp.size_y,
+                z: resp.size_z,
+            },
+            can_shoot_auto_upper: resp.can_shoot_auto_upper,
+            can_shoot_auto_lower: resp.can_shoot_auto_lower,
+            can_shoot_teleop_upper: resp.can_shoot_teleop_upper,
+            can_shoot_teleop_lower: resp.can_shoot_teleop_lower,
+            climb: resp.climb,
+            comment: resp.comment,
+            build_quality: resp.build_quality,
+            driver_team: resp.driver_team,
+            confidence: resp.confidence,
+            picture: String::new(),
+            images: serde_json::from_str(&resp.image_ids).unwrap_or_default(),
+            submission_id: None,
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! Compares what teams say in the pits with what scouts see them do in matches.
+
+use serde::Serialize;
+
+use crate::{db::PitResponse, game::FormIngest, season::FieldValues};
+
+/// A yes/no pit claim and the match field that would show it.
+struct Claim {
+    capability: &'static str,
+    claimed: fn(&PitResponse) -> bool,
+    field: &'static str,
+}
+
+const CLAIMS: &[Claim] = &[
+    Claim {
+        capability: "auto_upper",
+        claimed: |p| p.can_shoot_auto_upper,
+        field: "auto_scored_upper",
+    },
+    Claim {
+        capability: "auto_lower",
+        claimed: |p| p.can_shoot_auto_lower,
+        field: "auto_scored_lower",
+    },
+    Claim {
+        capability: "teleop_upper",
+        claimed: |p| p.can_shoot_teleop_upper,
+        field: "teleop_scored_upper",
+    },
+    Claim {
+        capability: "teleop_lower",
+        claimed: |p| p.can_shoot_teleop_lower,
+        field: "teleop_scored_lower",
+    },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// Claimed in the pits but never seen in a match.
+    Overstated,
+    /// Seen in a match but not claimed in the pits.
+    Understated,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Discrepancy {
+    pub team: u32,
+    pub capability: &'static str,
+    pub kind: Kind,
+    /// What the pit form said: 1 or 0 for yes/no capabilities, the rung for the climb.
+    pub claimed: i64,
+    /// The best the team has shown in a match.
+    pub observed_best: i64,
+    pub matches: usize,
+    /// Matches that showed the capability, or for the climb, reached at least the claimed rung.
+    pub supporting: usize,
+}
+
+/// Checks one team's latest pit response against its match observations. Teams with no
+/// observed matches are never reported, since there is nothing to contradict the pit form.
+pub fn check(team: u32, pit: &PitResponse, observed: &[FormIngest]) -> Vec<Discrepancy> {
+    let mut found = Vec::new();
+    if observed.is_empty() {
+        return found;
+    }
+
+    let values = |field: &'static str| observed.iter().map(move |o| o.value(field).unwrap_or(0));
+
+    for claim in CLAIMS {
+        let claimed = (claim.claimed)(pit);
+        let best = values(claim.field).max().unwrap_or(0);
+        let supporting = values(claim.field).filter(|&v| v > 0).count();
+
+        let kind = match (claimed, supporting > 0) {
+            (true, false) => Kind::Overstated,
+            (false, true) => Kind::Understated,
+            _ => continue,
+        };
+
+        found.push(Discrepancy {
+            team,
+            capability: claim.capability,
+            kind,
+            claimed: claimed as i64,
+            observed_best: best,
+            matches: observed.len(),
+            supporting,
+        });
+    }
+
+    let stated = pit.climb as i64;
+    let best = values("climb").max().unwrap_or(-1);
+    let kind = if best < stated {
+        Some(Kind::Overstated)
+    } else if best > stated {
+        Some(Kind::Understated)
+    } else {
+        None
+    };
+
+    if let Some(kind) = kind {
+        found.push(Discrepancy {
+            team,
+            capability: "climb",
+            kind,
+            claimed: stated,
+            observed_best: best,
+            matches: observed.len(),
+            supporting: values("climb").filter(|&v| v >= stated).count(),
+        });
+    }
+
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::match_response;
+
+    /// A pit response claiming only upper shots in auto and teleop, and the given climb.
+    fn pit(climb: i8) -> PitResponse {
+        PitResponse {
+            id: 1,
+            timestamp: 0,
+            uuid: 1,
+            team: 254,
+            team_name: "Test".to_string(),
+            drivetrain: "swerve".to_string(),
+            weight: 110,
+            size_x: 30.0,
+            size_y: 30.0,
+            size_z: 40.0,
+            can_shoot_auto_upper: true,
+            can_shoot_auto_lower: false,
+            can_shoot_teleop_upper: true,
+            can_shoot_teleop_lower: false,
+            climb,
+            build_quality: 5,
+            confidence: 5,
+            driver_team: 5,
+            comment: String::new(),
+            image_ids: "[]".to_string(),
+        }
+    }
+
+    fn observed(auto_upper: i16, teleop_upper: i16, teleop_lower: i16, climb: i8) -> FormIngest {
+        let mut r = match_response(1, 254);
+        r.auto_scored_upper = auto_upper;
+        r.teleop_scored_upper = teleop_upper;
+        r.teleop_scored_lower = teleop_lower;
+        r.climb = climb;
+        r.into()
+    }
+
+    fn found(pit: &PitResponse, observed: &[FormIngest]) -> Vec<(&'static str, Kind)> {
+        check(254, pit, observed)
+            .into_iter()
+            .map(|d| (d.capability, d.kind))
+            .collect()
+    }
+
+    #[test]
+    fn matching_claims_are_not_reported() {
+        let matches = [observed(1, 5, 0, 2), observed(0, 3, 0, 1)];
+        assert!(found(&pit(2), &matches).is_empty());
+    }
+
+    #[test]
+    fn claims_never_seen_are_overstated() {
+        let matches = [observed(0, 5, 0, 2), observed(0, 3, 0, 2)];
+        let discrepancies = check(254, &pit(2), &matches);
+
+        assert_eq!(discrepancies.len(), 1);
+        let d = &discrepancies[0];
+        assert_eq!((d.capability, d.kind), ("auto_upper", Kind::Overstated));
+        assert_eq!((d.claimed, d.observed_best), (1, 0));
+        assert_eq!((d.matches, d.supporting), (2, 0));
+    }
+
+    #[test]
+    fn unclaimed_abilities_seen_in_matches_are_understated() {
+        let matches = [observed(1, 5, 0, 2), observed(1, 3, 4, 2)];
+        let discrepancies = check(254, &pit(2), &matches);
+
+        assert_eq!(discrepancies.len(), 1);
+        let d = &discrepancies[0];
+        assert_eq!((d.capability, d.kind), ("teleop_lower", Kind::Understated));
+        assert_eq!((d.claimed, d.observed_best), (0, 4));
+        assert_eq!((d.matches, d.supporting), (2, 1));
+    }
+
+    #[test]
+    fn climbs_compare_against_the_best_rung_reached() {
+        let matches = [observed(1, 5, 0, 1), observed(1, 3, 0, 2)];
+
+        let high = check(254, &pit(3), &matches);
+        assert_eq!(high.len(), 1);
+        assert_eq!(
+            (high[0].capability, high[0].kind),
+            ("climb", Kind::Overstated)
+        );
+        assert_eq!((high[0].observed_best, high[0].supporting), (2, 0));
+
+        let low = check(254, &pit(1), &matches);
+        assert_eq!(low.len(), 1);
+        assert_eq!(
+            (low[0].capability, low[0].kind),
+            ("climb", Kind::Understated)
+        );
+        assert_eq!((low[0].observed_best, low[0].supporting), (2, 2));
+    }
+
+    #[test]
+    fn no_climb_claimed_or_seen_is_consistent() {
+        let matches = [observed(1, 5, 0, -1)];
+        assert!(found(&pit(-1), &matches).is_empty());
+    }
+
+    #[test]
+    fn teams_without_matches_are_never_reported() {
+        assert!(found(&pit(3), &[]).is_empty());
+    }
+}
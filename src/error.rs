@@ -0,0 +1,259 @@
+//! Errors returned by the API, as JSON with a stable code.
+//!
+//! Clients should branch on `code` rather than the message or status, and use `retryable`
+//! to tell a form that has to be fixed from a server that will accept it later.
+
+use std::fmt;
+
+use actix_web::{
+    dev::HttpResponseBuilder,
+    http::{header::ETAG, StatusCode},
+    HttpResponse, ResponseError,
+};
+use serde::Serialize;
+use specialscout_db::{
+    images::ImageError,
+    validation::{Issue, Severity},
+};
+
+#[derive(Debug)]
+pub enum ApiError {
+    /// The request or submitted form is wrong, and sending it again unchanged won't help.
+    Invalid(String),
+    /// A submitted form failed validation. Holds every issue found, warnings included.
+    Validation(Vec<Issue>),
+    /// Something with the same identity already exists.
+    Duplicate(String),
+    /// The request was made against a version that has since changed. `etag` names the
+    /// current one, so the client knows to fetch it again before retrying.
+    Conflict {
+        message: String,
+        etag: String,
+    },
+    NotFound(String),
+    /// No credentials, or credentials the server doesn't recognise.
+    Unauthorized(String),
+    /// Valid credentials that don't allow this request.
+    Forbidden(String),
+    TooLarge(String),
+    /// The database failed. `context` says what the server was doing at the time, and is all
+    /// the client sees; `source` is only logged.
+    Storage {
+        context: &'static str,
+        source: sqlx::Error,
+    },
+    Internal(String),
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    code: &'static str,
+    message: String,
+    retryable: bool,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    fields: &'a [Issue],
+}
+
+impl ApiError {
+    pub fn storage(context: &'static str, source: sqlx::Error) -> Self {
+        ApiError::Storage { context, source }
+    }
+
+    /// Logs a server-side failure and hides it behind `context`, so clients never see paths
+    /// or library errors.
+    pub fn internal(context: &'static str, source: impl fmt::Display) -> Self {
+        log::error!("{}: {}", context, source);
+        ApiError::Internal(context.to_string())
+    }
+
+    /// The machine-readable code sent to clients. These are part of the API, so existing ones
+    /// must never change.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Invalid(_) => "invalid_request",
+            ApiError::Validation(_) => "validation_failed",
+            ApiError::Duplicate(_) => "duplicate",
+            ApiError::Conflict { .. } => "conflict",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::TooLarge(_) => "payload_too_large",
+            ApiError::Storage { source, .. } if is_unavailable(source) => "storage_unavailable",
+            ApiError::Storage { .. } => "storage_error",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    /// Per-field detail, for validation failures.
+    pub fn fields(&self) -> &[Issue] {
+        match self {
+            ApiError::Validation(issues) => issues,
+            _ => &[],
+        }
+    }
+
+    /// Logs what a storage failure hid from the client. Call it wherever the error is
+    /// reported some other way than as a response.
+    pub fn log_source(&self) {
+        if let ApiError::Storage { context, source } = self {
+            log::error!("{}: {}", context, source);
+        }
+    }
+
+    /// Whether the same request might succeed later. Only server-side failures qualify.
+    pub fn retryable(&self) -> bool {
+        self.status_code().is_server_error()
+    }
+}
+
+/// Failures that come from the database being busy or unreachable rather than the query.
+fn is_unavailable(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed | sqlx::Error::Io(_) => true,
+        // SQLITE_BUSY and SQLITE_LOCKED, when another writer holds the file
+        sqlx::Error::Database(e) => matches!(e.code().as_deref(), Some("5") | Some("6")),
+        _ => false,
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Invalid(message)
+            | ApiError::Duplicate(message)
+            | ApiError::NotFound(message)
+            | ApiError::Unauthorized(message)
+            | ApiError::Forbidden(message)
+            | ApiError::TooLarge(message)
+            | ApiError::Internal(message)
+            | ApiError::Conflict { message, .. } => f.write_str(message),
+            ApiError::Validation(issues) => {
+                let errors: Vec<String> = issues
+                    .iter()
+                    .filter(|i| i.severity == Severity::Error)
+                    .map(|i| format!("{} {}", i.field, i.message))
+                    .collect();
+                write!(f, "Invalid form: {}", errors.join("; "))
+            }
+            ApiError::Storage { context, .. } => f.write_str(context),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::Invalid(_) | ApiError::Validation(_) => StatusCode::BAD_REQUEST,
+            ApiError::Duplicate(_) | ApiError::Conflict { .. } => StatusCode::CONFLICT,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::Storage { source, .. } if is_unavailable(source) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            ApiError::Storage { .. } | ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        self.log_source();
+
+        let mut response = HttpResponseBuilder::new(self.status_code());
+        if let ApiError::Conflict { etag, .. } = self {
+            response.header(ETAG, etag.as_str());
+        }
+
+        response.json(ErrorBody {
+            code: self.code(),
+            message: self.to_string(),
+            retryable: self.retryable(),
+            fields: self.fields(),
+        })
+    }
+}
+
+/// For `?` on queries where the step that failed is clear enough from the route.
+impl From<sqlx::Error> for ApiError {
+    fn from(e: sqlx::Error) -> Self {
+        ApiError::storage("Database error", e)
+    }
+}
+
+impl From<ImageError> for ApiError {
+    fn from(e: ImageError) -> Self {
+        if e.is_bad_request() {
+            ApiError::Invalid(e.to_string())
+        } else {
+            ApiError::internal("Failed storing image", e)
+        }
+    }
+}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(e: serde_json::Error) -> Self {
+        ApiError::internal("Failed encoding JSON", e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::body::{Body, ResponseBody};
+    use serde_json::Value;
+
+    use super::*;
+
+    /// The JSON body a client would receive for `error`.
+    fn body(error: &ApiError) -> Value {
+        match error.error_response().body() {
+            ResponseBody::Body(Body::Bytes(bytes)) => serde_json::from_slice(bytes).unwrap(),
+            _ => panic!("error bodies are sent in one piece"),
+        }
+    }
+
+    #[test]
+    fn conflict_names_the_current_version() {
+        let error = ApiError::Conflict {
+            message: "Pick list is at version 4, not 3".to_string(),
+            etag: "\"4\"".to_string(),
+        };
+
+        let response = error.error_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(response.headers().get(ETAG).unwrap(), "\"4\"");
+
+        let body = body(&error);
+        assert_eq!(body["code"], "conflict");
+        assert_eq!(body["message"], "Pick list is at version 4, not 3");
+        assert_eq!(body["retryable"], false);
+    }
+
+    #[test]
+    fn storage_errors_only_tell_clients_the_context() {
+        let error = ApiError::storage(
+            "Failed reading team details",
+            sqlx::Error::Protocol("no such column: secret_column".to_string()),
+        );
+
+        let body = body(&error);
+        assert_eq!(body["code"], "storage_error");
+        assert_eq!(body["message"], "Failed reading team details");
+        assert_eq!(body["retryable"], true);
+        assert!(!body.to_string().contains("secret_column"));
+    }
+
+    #[test]
+    fn internal_errors_hide_their_source() {
+        let io = std::io::Error::other("/srv/images/ab.partial: disk full");
+
+        let body = body(&ApiError::from(ImageError::Io(io)));
+        assert_eq!(body["code"], "internal_error");
+        assert_eq!(body["message"], "Failed storing image");
+        assert!(!body.to_string().contains("/srv/images"));
+
+        // a bad upload is the client's fault, so it is told what was wrong
+        let rejected = self::body(&ApiError::from(ImageError::NotAnImage));
+        assert_eq!(rejected["code"], "invalid_request");
+        assert_eq!(rejected["message"], "not a PNG, JPEG, GIF or WebP image");
+    }
+}
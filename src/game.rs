@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::season::{FieldValues, Game, RapidReact};
+
 #[derive(Debug, Deserialize, Serialize, Clone, Copy)]
 pub struct DetailedTeam {
     pub team: Team,
@@ -12,6 +14,15 @@ pub struct DetailedTeam {
     pub driv: f32,
     pub conf: f32, 
     pub avg_score: f32,
+    pub avg_auto_upper: f32,
+    pub avg_auto_lower: f32,
+    pub teleop_accuracy: f32,
+    pub taxi: bool,
+    pub preload: bool,
+    pub auto_shoot: bool,
+    pub stated_auto_shoot: bool,
+    pub climb: i8,
+    pub stated_climb: i8,
     pub rp: i16,
     pub prev_points: i16,
     pub est_points: i16
@@ -24,6 +35,12 @@ pub struct Team {
     number: u16,
 }
 
+impl Team {
+    pub fn number(&self) -> u16 {
+        self.number
+    }
+}
+
 impl From<u16> for Team {
     fn from(x: u16) -> Self {
         Team { number: x }
@@ -63,6 +80,11 @@ pub enum FormIngest {
         comments: String,
         red_score: i32,
         blue_score: i32,
+        #[serde(default)]
+        alliance: Option<Alliance>,
+        /// Client-generated ID, so retried submissions are only stored once.
+        #[serde(default)]
+        submission_id: Option<String>,
     },
     Pit {
         time_stamp: i32,
@@ -84,34 +106,94 @@ pub enum FormIngest {
         build_quality: i16,
         driver_team: i16,
         confidence: i16,
-        picture: String
+        /// Inline photo as a `data:` URL or base64, from clients that predate image uploads.
+        #[serde(default)]
+        picture: String,
+        /// Photos already uploaded to `/teams/{number}/images`.
+        #[serde(default)]
+        images: Vec<String>,
+        #[serde(default)]
+        submission_id: Option<String>,
     }
 }
 
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Alliance {
+    Red,
+    Blue,
+}
+
 #[derive(Deserialize, Serialize, Clone, Copy, Debug)]
 pub struct Size { pub x: f32, pub y: f32, pub z: f32 }
 
+/// 2022 score estimate for a single robot. Kept for callers that only have the raw fields;
+/// prefer [`Game::score`] with the active season.
 pub fn sim_score(did_taxi: bool, auto_scored_upper: i16, auto_scored_lower: i16, teleop_scored_upper: i16, teleop_scored_lower: i16, climb: i8) -> i64 {
-    let mut accum: i64 = 0;
+    let values: &[(&str, i64)] = &[
+        ("did_taxi", did_taxi as i64),
+        ("auto_scored_upper", auto_scored_upper as i64),
+        ("auto_scored_lower", auto_scored_lower as i64),
+        ("teleop_scored_upper", teleop_scored_upper as i64),
+        ("teleop_scored_lower", teleop_scored_lower as i64),
+        ("climb", climb as i64),
+    ];
+
+    RapidReact.score(&values)
+}
 
-    if did_taxi {
-        accum += 2;
+impl FieldValues for FormIngest {
+    fn value(&self, field: &str) -> Option<i64> {
+        match self {
+            FormIngest::Match {
+                timestamp,
+                match_number,
+                team_number,
+                did_preload,
+                did_taxi,
+                got_field_cargo,
+                did_hp_shot,
+                did_hp_sink,
+                auto_scored_lower,
+                auto_scored_upper,
+                auto_shots,
+                teleop_scored_lower,
+                teleop_scored_upper,
+                teleop_shots,
+                pins,
+                times_pinned,
+                penalties,
+                climb,
+                performance,
+                red_score,
+                blue_score,
+                ..
+            } => Some(match field {
+                "timestamp" => *timestamp as i64,
+                "match_number" => *match_number as i64,
+                "team_number" => *team_number as i64,
+                "did_preload" => *did_preload as i64,
+                "did_taxi" => *did_taxi as i64,
+                "got_field_cargo" => *got_field_cargo as i64,
+                "did_hp_shot" => *did_hp_shot as i64,
+                "did_hp_sink" => *did_hp_sink as i64,
+                "auto_scored_lower" => *auto_scored_lower as i64,
+                "auto_scored_upper" => *auto_scored_upper as i64,
+                "auto_shots" => *auto_shots as i64,
+                "teleop_scored_lower" => *teleop_scored_lower as i64,
+                "teleop_scored_upper" => *teleop_scored_upper as i64,
+                "teleop_shots" => *teleop_shots as i64,
+                "pins" => *pins as i64,
+                "times_pinned" => *times_pinned as i64,
+                "penalties" => *penalties as i64,
+                "climb" => *climb as i64,
+                "performance" => *performance as i64,
+                "red_score" => *red_score as i64,
+                "blue_score" => *blue_score as i64,
+                _ => return None,
+            }),
+            FormIngest::Pit { .. } => None,
+        }
     }
-
-    accum += (auto_scored_upper as i64) * 4;
-    accum += (auto_scored_lower as i64) * 2;
-    
-    accum += (teleop_scored_upper as i64) * 2;
-    accum += (teleop_scored_lower as i64) * 1;
-
-    accum += match climb {
-        -1 => 0,
-        0 => 4,
-        1 => 6,
-        2 => 10,
-        3 => 15,
-        _ => 0
-    };
-
-    accum
-}
\ No newline at end of file
+}
@@ -0,0 +1,359 @@
+use std::{
+    fmt, fs,
+    io::{self, Cursor},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use image::{io::Reader, DynamicImage, ImageOutputFormat};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// A decoded team picture, ready to be sent back to a client.
+pub struct Picture {
+    pub bytes: Vec<u8>,
+    pub content_type: String,
+}
+
+/// Pictures come in from the pit form as strings, either as a `data:` URL or as bare base64.
+/// Anything that is neither is assumed to already be raw image bytes.
+pub fn decode_picture(raw: &[u8]) -> Picture {
+    if let Some(url) = raw.strip_prefix(b"data:") {
+        if let Some(comma) = url.iter().position(|&b| b == b',') {
+            let (header, payload) = (&url[..comma], &url[comma + 1..]);
+            let header = String::from_utf8_lossy(header);
+            let mime = header.trim_end_matches(";base64");
+
+            let bytes = if header.ends_with(";base64") {
+                base64::decode(payload).unwrap_or_else(|_| payload.to_vec())
+            } else {
+                payload.to_vec()
+            };
+
+            let content_type = if mime.is_empty() {
+                sniff_content_type(&bytes).to_string()
+            } else {
+                mime.to_string()
+            };
+
+            return Picture { bytes, content_type };
+        }
+    }
+
+    let bytes = base64::decode(raw).unwrap_or_else(|_| raw.to_vec());
+    let content_type = sniff_content_type(&bytes).to_string();
+
+    Picture { bytes, content_type }
+}
+
+/// Guesses an image's content type from its magic bytes.
+pub fn sniff_content_type(bytes: &[u8]) -> &'static str {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        "image/png"
+    } else if bytes.starts_with(b"\xff\xd8\xff") {
+        "image/jpeg"
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        "image/gif"
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        "image/webp"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+/// Longest edge of a generated thumbnail, in pixels.
+pub const THUMBNAIL_SIZE: u32 = 320;
+
+/// Most pixels an image may have. Checked from the header before decoding, since a small
+/// file can claim a huge canvas. Leaves room for a 48 megapixel phone photo.
+pub const MAX_PIXELS: u64 = 50_000_000;
+
+/// An uploaded team photo, as recorded in `team_images`.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct StoredImage {
+    pub id: String,
+    pub team: u32,
+    pub content_type: String,
+    pub size: i64,
+    pub uploaded_at: i64,
+    pub uploaded_by: Option<u32>,
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    NotAnImage,
+    /// A client referred to an image by something that cannot be an id.
+    InvalidId(String),
+    /// More pixels than [`MAX_PIXELS`].
+    TooLarge { width: u32, height: u32 },
+    Decode(image::ImageError),
+    Io(io::Error),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::NotAnImage => write!(f, "not a PNG, JPEG, GIF or WebP image"),
+            ImageError::InvalidId(id) => write!(f, "{} is not an image id", id),
+            ImageError::TooLarge { width, height } => write!(
+                f,
+                "image is {}x{}, more than {} pixels",
+                width, height, MAX_PIXELS
+            ),
+            ImageError::Decode(e) => write!(f, "could not decode image: {}", e),
+            ImageError::Io(e) => write!(f, "could not store image: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+impl ImageError {
+    /// Whether the client sent something wrong, rather than the store failing.
+    pub fn is_bad_request(&self) -> bool {
+        !matches!(self, ImageError::Io(_))
+    }
+}
+
+impl From<io::Error> for ImageError {
+    fn from(e: io::Error) -> Self {
+        ImageError::Io(e)
+    }
+}
+
+/// Team photos on disk, each named by the SHA-256 of its contents.
+///
+/// The same photo uploaded twice is only stored once, and a name can never start pointing at
+/// different bytes, which is what lets clients cache images forever.
+#[derive(Debug, Clone)]
+pub struct ImageStore {
+    root: PathBuf,
+}
+
+impl ImageStore {
+    pub fn open(root: impl Into<PathBuf>) -> io::Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(root.join("thumbs"))?;
+        Ok(ImageStore { root })
+    }
+
+    /// Whether `id` could have come from this store. Checked before an id goes near a path.
+    pub fn is_valid_id(id: &str) -> bool {
+        id.len() == 64 && id.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    }
+
+    pub fn path(&self, id: &str) -> PathBuf {
+        self.root.join(&id[..2]).join(id)
+    }
+
+    pub fn thumbnail_path(&self, id: &str) -> PathBuf {
+        self.root.join("thumbs").join(format!("{}.jpg", id))
+    }
+
+    /// Stores an image and a JPEG thumbnail of it, returning its id and content type.
+    /// Blocks on disk and decoding, so call it off the async executor.
+    pub fn save(&self, bytes: &[u8]) -> Result<(String, &'static str), ImageError> {
+        let content_type = sniff_content_type(bytes);
+        if !content_type.starts_with("image/") {
+            return Err(ImageError::NotAnImage);
+        }
+
+        let id = hex::encode(Sha256::digest(bytes));
+        let path = self.path(&id);
+        let thumbnail_path = self.thumbnail_path(&id);
+
+        if !thumbnail_path.exists() {
+            let (width, height) = Reader::new(Cursor::new(bytes))
+                .with_guessed_format()?
+                .into_dimensions()
+                .map_err(ImageError::Decode)?;
+            if width as u64 * height as u64 > MAX_PIXELS {
+                return Err(ImageError::TooLarge { width, height });
+            }
+
+            let decoded = image::load_from_memory(bytes).map_err(ImageError::Decode)?;
+            let thumbnail = DynamicImage::ImageRgb8(
+                decoded.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).to_rgb8(),
+            );
+
+            let mut encoded = Vec::new();
+            thumbnail
+                .write_to(&mut encoded, ImageOutputFormat::Jpeg(80))
+                .map_err(ImageError::Decode)?;
+            write_atomically(&thumbnail_path, &encoded)?;
+        }
+
+        if !path.exists() {
+            fs::create_dir_all(path.parent().unwrap())?;
+            write_atomically(&path, bytes)?;
+        }
+
+        Ok((id, content_type))
+    }
+}
+
+/// Writes through a temporary file, so a crash never leaves a truncated image under its id.
+/// Every write gets its own temporary file, since two uploads of one photo can race.
+fn write_atomically(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    static WRITES: AtomicUsize = AtomicUsize::new(0);
+
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(format!(
+        ".{}-{}.partial",
+        process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let partial = PathBuf::from(partial);
+
+    if let Err(e) = fs::write(&partial, bytes) {
+        let _ = fs::remove_file(&partial);
+        return Err(e);
+    }
+    fs::rename(partial, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::GenericImageView;
+
+    use super::*;
+
+    /// A scratch image store, removed when dropped.
+    struct Scratch(ImageStore);
+
+    impl Scratch {
+        fn new(name: &str) -> Self {
+            let dir = format!("specialscout-images-{}-{}", name, process::id());
+            let root = std::env::temp_dir().join(dir);
+            let _ = fs::remove_dir_all(&root);
+            Scratch(ImageStore::open(root).unwrap())
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0.root);
+        }
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        DynamicImage::new_rgb8(width, height)
+            .write_to(&mut bytes, ImageOutputFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn sniffs_each_supported_format() {
+        assert_eq!(sniff_content_type(&png(1, 1)), "image/png");
+        assert_eq!(sniff_content_type(b"\xff\xd8\xff\xe0 jfif"), "image/jpeg");
+        assert_eq!(sniff_content_type(b"GIF87a..."), "image/gif");
+        assert_eq!(sniff_content_type(b"GIF89a..."), "image/gif");
+        assert_eq!(sniff_content_type(b"RIFF\0\0\0\0WEBPVP8 "), "image/webp");
+        assert_eq!(
+            sniff_content_type(b"RIFF\0\0\0\0WAVE"),
+            "application/octet-stream"
+        );
+        assert_eq!(sniff_content_type(b""), "application/octet-stream");
+    }
+
+    #[test]
+    fn decodes_data_urls() {
+        let encoded = format!("data:image/png;base64,{}", base64::encode(png(1, 1)));
+        let picture = decode_picture(encoded.as_bytes());
+        assert_eq!(picture.bytes, png(1, 1));
+        assert_eq!(picture.content_type, "image/png");
+
+        // without a type the bytes are sniffed, and without base64 they're taken as they are
+        let picture = decode_picture(b"data:,GIF89a...");
+        assert_eq!(picture.bytes, b"GIF89a...");
+        assert_eq!(picture.content_type, "image/gif");
+    }
+
+    #[test]
+    fn decodes_bare_base64_and_raw_bytes() {
+        let picture = decode_picture(base64::encode(png(1, 1)).as_bytes());
+        assert_eq!(picture.bytes, png(1, 1));
+        assert_eq!(picture.content_type, "image/png");
+
+        let picture = decode_picture(&png(1, 1));
+        assert_eq!(picture.bytes, png(1, 1));
+        assert_eq!(picture.content_type, "image/png");
+    }
+
+    #[test]
+    fn saves_once_under_the_content_hash() {
+        let scratch = Scratch::new("save");
+        let store = &scratch.0;
+        let bytes = png(640, 480);
+
+        let (id, content_type) = store.save(&bytes).unwrap();
+        assert!(ImageStore::is_valid_id(&id));
+        assert_eq!(content_type, "image/png");
+        assert_eq!(fs::read(store.path(&id)).unwrap(), bytes);
+
+        let thumbnail = image::open(store.thumbnail_path(&id)).unwrap();
+        assert_eq!(thumbnail.dimensions(), (THUMBNAIL_SIZE, 240));
+
+        assert_eq!(store.save(&bytes).unwrap().0, id);
+        let leftovers = fs::read_dir(store.path(&id).parent().unwrap())
+            .unwrap()
+            .count();
+        assert_eq!(leftovers, 1);
+    }
+
+    #[test]
+    fn rejects_images_over_max_pixels_before_decoding() {
+        let scratch = Scratch::new("huge");
+
+        // a GIF header claiming a 10000x10000 canvas, with nothing behind it
+        let mut huge = b"GIF89a".to_vec();
+        huge.extend_from_slice(&10_000u16.to_le_bytes());
+        huge.extend_from_slice(&10_000u16.to_le_bytes());
+        huge.extend_from_slice(&[0, 0, 0, 0x3b]);
+
+        let error = scratch.0.save(&huge).unwrap_err();
+        assert!(matches!(
+            error,
+            ImageError::TooLarge {
+                width: 10_000,
+                height: 10_000
+            }
+        ));
+        assert!(error.is_bad_request());
+    }
+
+    #[test]
+    fn rejects_what_is_not_an_image() {
+        let scratch = Scratch::new("text");
+        assert!(matches!(
+            scratch.0.save(b"hello"),
+            Err(ImageError::NotAnImage)
+        ));
+    }
+
+    #[test]
+    fn concurrent_writes_of_one_file_both_land() {
+        let scratch = Scratch::new("race");
+        let path = scratch.0.root.join("same");
+
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                let path = path.clone();
+                std::thread::spawn(move || write_atomically(&path, b"bytes"))
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap().unwrap();
+        }
+
+        assert_eq!(fs::read(&path).unwrap(), b"bytes");
+        let partials = fs::read_dir(&scratch.0.root)
+            .unwrap()
+            .filter(|e| e.as_ref().unwrap().path().extension() == Some("partial".as_ref()))
+            .count();
+        assert_eq!(partials, 0);
+    }
+}
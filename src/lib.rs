@@ -1 +1,22 @@
-pub mod game;
\ No newline at end of file
+pub mod aggregate;
+pub mod audit;
+pub mod config;
+pub mod consensus;
+pub mod coverage;
+pub mod db;
+pub mod discrepancy;
+pub mod game;
+pub mod images;
+pub mod migrations;
+pub mod opr;
+pub mod picklist;
+pub mod predict;
+pub mod ranking;
+pub mod reliability;
+pub mod schedule;
+pub mod scouts;
+pub mod season;
+pub mod stats;
+#[cfg(test)]
+mod testing;
+pub mod validation;
@@ -1,88 +1,121 @@
 
 use actix_web::{
     self,
+    middleware::Logger,
     web::{self, Json},
-    App, Error, HttpResponse, HttpServer, Responder,
+    App, Error, HttpRequest, HttpResponse, HttpServer, Responder,
+};
+use serde::{Deserialize, Serialize};
+use specialscout_db::{
+    aggregate,
+    config::{self, Command, Config},
+    game::FormIngest,
+    images::{decode_picture, ImageStore},
+    migrations,
+    season::{Game, Rules},
+    validation::{self, Issue, Severity},
 };
-use serde::Deserialize;
-use specialscout_db::game::{FormIngest, sim_score};
 use sqlx::{
     Acquire,
-    query,
-    sqlite::{SqliteConnectOptions, SqlitePool},
-    ConnectOptions, Connection, Executor, pool::PoolConnection,
+    query, query_scalar,
+    sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions},
+    ConnectOptions, Connection, SqliteConnection,
 };
 use std::{
-    fs, io,
-    time::{SystemTime, UNIX_EPOCH}, cmp::max
+    env, fs, io,
+    path::Path,
+    process,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
-const IP: &str = "0.0.0.0:80";
-const DB_FILE: &str = "db.sqlite";
+mod error;
+mod routes;
+
+use error::ApiError;
 
 struct AppState {
     db: SqlitePool,
-}
-
-struct TeamDetails {
-    team: i32,
-    matches: i32,
-    taxi: i32,
-    taxi_true: i32,
-    preload: i32,
-    auto_shoot: i32,
-    auto_shoot_true: i32,
-    shots_accum: i32,
-    shots_upper_accum: i32,
-    shots_lower_accum: i32,
-    climb: i32,
-    stated_climb: i32,
-    score_accum: i32
+    game: &'static dyn Game,
+    images: ImageStore,
+    config: Config,
 }
 
 async fn heartbeat() -> impl Responder {
     HttpResponse::Ok().body(format!("specialscout-db v{}", env!("CARGO_PKG_VERSION")))
 }
 
+async fn rules(data: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(Rules::from(data.game))
+}
+
 #[derive(Deserialize)]
 struct ResponseDump {
     responses: Vec<FormIngest>,
 }
 
-fn sqlx_to_actix(format_string: &str, error: sqlx::Error) -> HttpResponse {
-    HttpResponse::RequestTimeout().body(format!("{}\nError: {:?}", format_string, error))
-}
-
 #[actix_web::post("/dump_resps/{uuid}")]
 async fn dump_responses(
+    req: HttpRequest,
     web::Path((uuid,)): web::Path<(u32,)>,
     dump: Json<FormIngest>,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
-    let conn = data.db.acquire().await;
+    routes::scouts::authenticate(&req, uuid, &data.db).await?;
+
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
 
-    // sqlx::Error does not convert happily to actix_web's Error
-    if let Err(e) = conn {
-        return Err(sqlx_to_actix("Timed out connecting to DB", e).into());
-        // return Err
+    let known_events =
+        validation::known_events(&mut tx, data.config.event.as_deref(), &data.config.validation)
+            .await
+            .map_err(|e| ApiError::storage("Failed reading events", e))?;
+    let warnings = validate(&dump.0, &data, &known_events)?;
+
+    let stored = insert_response(&dump.0, uuid, data.game, &data.images, &mut tx).await?;
+
+    tx.commit()
+        .await
+        .map_err(|e| ApiError::storage("Failed committing response", e))?;
+
+    match stored {
+        Stored::Inserted if warnings.is_empty() => Ok(HttpResponse::NoContent().finish()),
+        Stored::Inserted => Ok(HttpResponse::Ok().json(IngestResult::Accepted { warnings })),
+        Stored::Duplicate => Ok(HttpResponse::Ok().json(IngestResult::Duplicate)),
     }
+}
 
-    let mut conn = conn.unwrap();
+/// Checks a form against the season and configured rules. Fails if anything is an error,
+/// otherwise hands back the warnings.
+fn validate(
+    ingest: &FormIngest,
+    data: &AppState,
+    known_events: &[String],
+) -> Result<Vec<Issue>, ApiError> {
+    let issues = validation::check(ingest, data.game, &data.config.validation, known_events);
 
-    if let Err(e) = insert_response(&dump.0, uuid, &mut conn).await {
-        Err(HttpResponse::InternalServerError().body(e.to_string()).into())
+    if issues.iter().any(|i| i.severity == Severity::Error) {
+        Err(ApiError::Validation(issues))
     } else {
-        Ok(HttpResponse::NoContent().finish())
+        Ok(issues)
     }
 }
 
+/// Whether `insert_response` stored a new response or recognised one it already had.
+enum Stored {
+    Inserted,
+    Duplicate,
+}
+
 async fn insert_response(
     ingest: &FormIngest,
     uuid: u32,
-    db: &mut PoolConnection<sqlx::Sqlite>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let conn = &mut *db.acquire().await?;
-
+    game: &dyn Game,
+    images: &ImageStore,
+    conn: &mut SqliteConnection,
+) -> Result<Stored, ApiError> {
     match ingest {
         FormIngest::Match {
             timestamp,
@@ -108,8 +141,34 @@ async fn insert_response(
             red_score,
             blue_score,
             climb,
+            alliance,
+            submission_id,
         } => {
-            query("INSERT INTO match_responses VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+            // tablets retry on flaky wifi, so a resent form must not be counted twice
+            let existing: i64 = query_scalar(
+                r#"SELECT COUNT(*) FROM match_responses
+                    WHERE submission_id = ?1
+                    OR (uuid = ?2 AND event = ?3 AND match_number = ?4 AND team_number = ?5)"#,
+            )
+            .bind(submission_id)
+            .bind(uuid)
+            .bind(event)
+            .bind(match_number)
+            .bind(team_number)
+            .fetch_one(&mut *conn)
+            .await?;
+
+            if existing > 0 {
+                return Ok(Stored::Duplicate);
+            }
+
+            query(r#"INSERT INTO match_responses (
+                    timestamp, uuid, event, match_number, team_number, did_preload, did_taxi,
+                    got_field_cargo, did_hp_shot, did_hp_sink, auto_scored_lower, auto_scored_upper,
+                    auto_shots, teleop_scored_lower, teleop_scored_upper, teleop_shots, pins,
+                    times_pinned, penalties, performance, red_score, blue_score, climb, comment, alliance,
+                    submission_id
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#)
                 .bind(timestamp)
                 .bind(uuid)
                 .bind(event)
@@ -134,77 +193,14 @@ async fn insert_response(
                 .bind(blue_score)
                 .bind(climb)
                 .bind(comments)
+                .bind(alliance)
+                .bind(submission_id)
                 .execute(conn.acquire().await?)
                 .await?;
-            
-            let maybe_old = query!(r#"SELECT * FROM team_details WHERE team = ?1"#, team_number).fetch_optional(&mut *conn).await?;
-
-            if let Some(mut details) = maybe_old {
-                details.matches += 1;
-                details.taxi_true |= *did_taxi as i64;
-                details.preload |= *did_preload as i64;
-                details.auto_shoot |= (*auto_shots > 0) as i64;
-                details.auto_shoot_true |= (*auto_shots > 0) as i64;
-                details.auto_upper_accum += *auto_scored_upper as i64;
-                details.auto_lower_accum += *auto_scored_lower as i64;
-                details.shots_accum += *teleop_shots as i64;
-                details.shots_upper_accum += *teleop_scored_upper as i64;
-                details.shots_lower_accum += *teleop_scored_lower as i64;
-                details.climb = max(details.climb, (*climb).into());
-                details.score_accum += sim_score(*did_taxi, *auto_scored_upper, *auto_scored_lower, *teleop_scored_upper, *teleop_scored_lower, *climb);
-                query!(r#"INSERT OR REPLACE INTO team_details VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)"#,
-                    team_number,
-                    details.matches,
-                    details.taxi,
-                    details.taxi_true,
-                    details.preload,
-                    details.auto_shoot,
-                    details.auto_shoot_true,
-                    details.auto_upper_accum,
-                    details.auto_lower_accum,
-                    details.shots_accum,
-                    details.shots_upper_accum,
-                    details.shots_lower_accum,
-                    details.climb,
-                    details.stated_climb,
-                    details.score_accum
-                ).execute(conn.acquire().await?)
-                .await?;
-            } else {
-                // initialize team details
-                let matches = 1i64;
-                let taxi_true = *did_taxi as i64;
-                let preload = *did_preload as i64;
-                let auto_shoot = (*auto_shots > 0) as i64;
-                let auto_shoot_true = (*auto_shots > 0) as i64;
-                let auto_upper_accum = *auto_scored_upper as i64;
-                let auto_lower_accum = *auto_scored_lower as i64;
-                let shots_accum = *teleop_shots as i64;
-                let shots_upper_accum = *teleop_scored_upper as i64;
-                let shots_lower_accum = *teleop_scored_lower as i64;
-                let score_accum = sim_score(*did_taxi, *auto_scored_upper, *auto_scored_lower, *teleop_scored_upper, *teleop_scored_lower, *climb);
-                query!(r#"INSERT OR REPLACE INTO team_details VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)"#,
-                    team_number,
-                    matches,
-                    taxi_true,
-                    taxi_true,
-                    preload,
-                    auto_shoot,
-                    auto_shoot_true,
-                    auto_upper_accum,
-                    auto_lower_accum,
-                    shots_accum,
-                    shots_upper_accum,
-                    shots_lower_accum,
-                    climb,
-                    climb,
-                    score_accum
-                ).execute(conn.acquire().await?)
-                .await?;
-            }
 
-    
-            Ok(())
+            aggregate::rebuild_team(conn, *team_number as i32, game).await?;
+
+            Ok(Stored::Inserted)
         }
         FormIngest::Pit {
             time_stamp,
@@ -223,8 +219,56 @@ async fn insert_response(
             driver_team,
             confidence,
             picture,
+            images: image_ids,
+            submission_id,
         } => {
-            query("INSERT INTO pit_responses VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+            routes::teams::check_team_images(conn, *team_number as u32, image_ids).await?;
+            let existing: i64 = query_scalar(
+                r#"SELECT COUNT(*) FROM pit_responses
+                    WHERE submission_id = ?1 OR (uuid = ?2 AND team = ?3 AND timestamp = ?4)"#,
+            )
+            .bind(submission_id)
+            .bind(uuid)
+            .bind(team_number)
+            .bind(time_stamp)
+            .fetch_one(&mut *conn)
+            .await?;
+
+            if existing > 0 {
+                return Ok(Stored::Duplicate);
+            }
+
+            let mut image_ids = image_ids.clone();
+            if !picture.is_empty() {
+                // old clients send whatever the camera gave them, so a bad picture shouldn't
+                // cost the rest of the form
+                let legacy = decode_picture(picture.as_bytes());
+                match routes::teams::store_image(
+                    images, legacy.bytes, *team_number as u32, Some(uuid), conn,
+                )
+                .await
+                {
+                    Ok(stored) => {
+                        if !image_ids.contains(&stored.id) {
+                            image_ids.push(stored.id);
+                        }
+                    }
+                    Err(ApiError::Invalid(e)) => {
+                        log::warn!(
+                            "Dropped picture from scout {} for team {}: {}",
+                            uuid, team_number, e
+                        );
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+
+            query(r#"INSERT INTO pit_responses (
+                    timestamp, uuid, team, team_name, drivetrain, weight, size_x, size_y, size_z,
+                    can_shoot_auto_lower, can_shoot_auto_upper, can_shoot_teleop_lower,
+                    can_shoot_teleop_upper, climb, build_quality, confidence, driver_team, comment,
+                    image_ids, submission_id
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#)
                 .bind(time_stamp)
                 .bind(uuid)
                 .bind(team_number)
@@ -243,282 +287,287 @@ async fn insert_response(
                 .bind(confidence)
                 .bind(driver_team)
                 .bind(comment)
-                .bind(picture)
+                .bind(serde_json::to_string(&image_ids)?)
+                .bind(submission_id)
                 .execute(&mut *conn)
                 .await?;
-            
-            let maybe_old = query!(r#"SELECT * FROM team_details WHERE team = ?1"#, team_number).fetch_optional(&mut *conn).await?;
-
-            if let None = maybe_old {
-                let team = team_number;  
-                let matches: i32 = 0;
-                let taxi: i32 = 0;
-                let taxi_true: i32 = 0;
-                let preload: i32 = 0;
-                let auto_shoot: i32 = (can_shoot_auto_lower | can_shoot_auto_upper) as i32;
-                let auto_shoot_true: i32 = 0;
-                let auto_upper_accum: i32 = 0;
-                let auto_lower_accum: i32 = 0;
-                let shots_accum: i32 = 0;
-                let shots_upper_accum: i32 = 0;
-                let shots_lower_accum: i32 = 0;
-                let climb: i32 = 0;
-                let stated_climb: i32 = climb;
-                let score_accum: i32 = 0;
-                query!(r#"INSERT OR REPLACE INTO team_details VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)"#,
-                    team,
-                    matches,
-                    taxi,
-                    taxi_true,
-                    preload,
-                    auto_shoot,
-                    auto_shoot_true,
-                    auto_upper_accum,
-                    auto_lower_accum,
-                    shots_accum,
-                    shots_upper_accum,
-                    shots_lower_accum,
-                    climb,
-                    stated_climb,
-                    score_accum
-                ).execute(conn.acquire().await?)
-                .await?;
-            } 
 
-            query!(r#"INSERT OR REPLACE INTO images VALUES (?1, ?2)"#, team_number, picture).execute(conn).await?;
+            aggregate::rebuild_team(conn, *team_number, game).await?;
 
-            Ok(())
+            Ok(Stored::Inserted)
         },
     }
 }
 
+/// What happened to a single response in a bulk dump.
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+enum IngestResult {
+    Accepted {
+        /// Values that were stored but look unlikely, for the scout to double check.
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        warnings: Vec<Issue>,
+    },
+    /// Already stored, either earlier in the same dump or by a previous submission.
+    Duplicate,
+    /// `code` is the one the error would have had on its own, see `ApiError::code`.
+    Rejected {
+        code: &'static str,
+        reason: String,
+        /// Per-field detail when the response failed validation.
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        fields: Vec<Issue>,
+    },
+}
+
+#[derive(Serialize)]
+struct DumpReport {
+    /// One entry per submitted response, in the order they were sent.
+    results: Vec<IngestResult>,
+}
+
 #[actix_web::post("/dump_resps_mass/{uuid}")]
 async fn dump_responses_mass(
+    req: HttpRequest,
     web::Path((uuid,)): web::Path<(u32,)>,
     dump: Json<ResponseDump>,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
-    let conn = data.db.acquire().await;
+    routes::scouts::authenticate(&req, uuid, &data.db).await?;
 
-    // sqlx::Error does not convert happily to actix_web's Error
-    if let Err(e) = conn {
-        return Ok(sqlx_to_actix("Timed out connecting to DB", e));
-        // return Err
-    }
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
+
+    let known_events =
+        validation::known_events(&mut tx, data.config.event.as_deref(), &data.config.validation)
+            .await
+            .map_err(|e| ApiError::storage("Failed reading events", e))?;
 
-    let mut conn = conn.unwrap();
+    let mut results = Vec::with_capacity(dump.responses.len());
 
     for ingest in &dump.responses {
-        if let Err(e) = insert_response(ingest, uuid, &mut conn).await {
-            return Ok(HttpResponse::InternalServerError().body(e.to_string()));
+        let warnings = match validate(ingest, &data, &known_events) {
+            Ok(warnings) => warnings,
+            Err(e) => {
+                results.push(IngestResult::Rejected {
+                    code: e.code(),
+                    reason: e.to_string(),
+                    fields: e.fields().to_vec(),
+                });
+                continue;
+            }
+        };
+
+        // each response gets a savepoint, so a bad one is rolled back without losing the rest
+        let mut item = tx
+            .begin()
+            .await
+            .map_err(|e| ApiError::storage("Failed starting savepoint", e))?;
+
+        match insert_response(ingest, uuid, data.game, &data.images, &mut item).await {
+            Ok(stored) => {
+                item.commit()
+                    .await
+                    .map_err(|e| ApiError::storage("Failed releasing savepoint", e))?;
+                results.push(match stored {
+                    Stored::Inserted => IngestResult::Accepted { warnings },
+                    Stored::Duplicate => IngestResult::Duplicate,
+                });
+            }
+            Err(e) => {
+                item.rollback()
+                    .await
+                    .map_err(|e| ApiError::storage("Failed rolling back savepoint", e))?;
+                e.log_source();
+                results.push(IngestResult::Rejected {
+                    code: e.code(),
+                    reason: e.to_string(),
+                    fields: Vec::new(),
+                });
+            }
         }
     }
 
-    Ok(HttpResponse::NoContent().finish())
+    tx.commit()
+        .await
+        .map_err(|e| ApiError::storage("Failed committing responses", e))?;
+
+    Ok(HttpResponse::Ok().json(DumpReport { results }))
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    println!("Hello, world!");
+    let config = match config::load(env::args().skip(1)) {
+        Ok(Command::Serve(config)) => config,
+        Ok(Command::PrintConfig(config)) => {
+            print!("{}", config.to_toml());
+            return Ok(());
+        }
+        Ok(Command::Help) => {
+            println!("{}", config::USAGE);
+            return Ok(());
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+
+    env_logger::Builder::new()
+        .parse_filters(&config.log_level)
+        .init();
+
+    if config.admin_token.is_none() {
+        if config.open_admin {
+            log::warn!("No admin_token is set and open_admin is on, so admin routes are open");
+        } else {
+            log::warn!(
+                "No admin_token is set, so admin routes are disabled. Scouts can't be registered \
+                 and so can't submit responses until one is set"
+            );
+        }
+    }
+
+    let db_file = config.database.as_str();
+    let existed = fs::metadata(db_file).is_ok();
 
     let initial_conn_options = SqliteConnectOptions::new()
-        .filename(DB_FILE)
-        .create_if_missing(false);
-    // let conn = SqliteConnection::connect(&format!("sqlite:{}", DB_FILE)).await;
-    let conn = initial_conn_options.connect().await;
-
-    if let Err(e) = conn {
-        println!(
-            "Ran into error {:?} while connecting to db! Assuming db is foobar, recreating...",
-            e
-        );
-
-        if fs::metadata(DB_FILE).is_ok() {
-            if fs::metadata(DB_FILE).is_ok() {
-                if let Err(e) = fs::copy(
-                    DB_FILE,
-                    format!(
-                        "{}_{}bak",
-                        SystemTime::now()
-                            .duration_since(UNIX_EPOCH)
-                            .unwrap()
-                            .as_millis(),
-                        DB_FILE
-                    ),
-                ) {
-                    println!("Could not create a backup of the DB file!");
-                    println!("{:?}", e);
-                    println!("Clear {}? > ", DB_FILE);
-                    let mut buffer = String::new();
-                    io::stdin()
-                        .read_line(&mut buffer)
-                        .expect("Could not read stdin, exiting to avoid clearing DB.");
-
-                    if !buffer.to_lowercase().contains("y") {
-                        println!("Please manually create a backup of {} and rerun.", DB_FILE);
-                        return Ok(());
-                    }
+        .filename(db_file)
+        .create_if_missing(true);
+    let mut conn = initial_conn_options
+        .connect()
+        .await
+        .expect("Could not open or create db file!");
+
+    let current = migrations::current_version(&mut conn)
+        .await
+        .expect("Could not read schema version!");
+
+    if current < migrations::latest_version() {
+        if existed {
+            let db_path = Path::new(db_file);
+            let backup = db_path.with_file_name(format!(
+                "{}_{}bak",
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_millis(),
+                db_path.file_name().unwrap_or_default().to_string_lossy()
+            ));
+            println!(
+                "{} is at schema version {}, backing up to {} before migrating...",
+                db_file,
+                current,
+                backup.display()
+            );
+
+            if let Err(e) = fs::copy(db_file, &backup) {
+                println!("Could not create a backup of the DB file!");
+                println!("{:?}", e);
+                println!("Migrate {} without a backup? > ", db_file);
+                let mut buffer = String::new();
+                io::stdin()
+                    .read_line(&mut buffer)
+                    .expect("Could not read stdin, exiting to avoid migrating without a backup.");
+
+                if !buffer.to_lowercase().contains('y') {
+                    println!("Please manually create a backup of {} and rerun.", db_file);
+                    return Ok(());
                 }
-                fs::remove_file(DB_FILE).expect(&format!(
-                    "Could not clear existing db file! Ensure you have write access to {}",
-                    DB_FILE
-                ))
             }
-            println!("Creating new {}...", DB_FILE);
-        } else {
-            println!("Could not write to this directory. Check permissions.");
-            return Ok(());
         }
 
-        let initial_conn_options = SqliteConnectOptions::new()
-            .filename(DB_FILE)
-            .create_if_missing(true);
-        let mut conn = initial_conn_options
-            .connect()
+        let applied = migrations::migrate(&mut conn)
             .await
-            .expect("Could not create db file!");
-
-        conn.execute(
-            r#"CREATE TABLE "uuids" (
-            "uuid"	INTEGER NOT NULL UNIQUE,
-            "name"	TEXT,
-            "team_number"	INTEGER,
-            PRIMARY KEY("uuid")
-        )"#,
-        )
-        .await
-        .expect("Failed creating uuids table!");
-
-        conn.execute(
-            r#"CREATE TABLE "teams" (
-                "team_number"	INTEGER NOT NULL,
-                "matches_played"	INTEGER NOT NULL DEFAULT 0,
-                "scouts"	INTEGER DEFAULT 0,
-                "responses"	INTEGER DEFAULT 0,
-                PRIMARY KEY("team_number")
-            );"#,
-        )
-        .await
-        .expect("Failed creating teams table!");
-
-        conn.execute(
-            r#"CREATE TABLE "matches" (
-                "event"	TEXT NOT NULL DEFAULT 'Unknown',
-                "match_number"	INTEGER,
-                "group"	TEXT,
-                "teams"	TEXT,
-                PRIMARY KEY("event")
-            )"#,
-        )
-        .await
-        .expect("Failed creating matches table!");
-
-        conn.execute(
-            r#"CREATE TABLE "match_responses" (
-                "timestamp"	INTEGER NOT NULL,
-                "uuid" INTEGER NOT NULL,
-                "event"	TEXT NOT NULL,
-                "team_number" INTEGER NOT NULL,
-                "match_number"	INTEGER NOT NULL,
-                "did_preload"	INTEGER NOT NULL,
-                "did_taxi"	INTEGER NOT NULL,
-                "got_field_cargo"	INTEGER NOT NULL,
-                "did_hp_shot"   INTEGER NOT NULL,
-                "did_hp_sink"   INTEGER NOT NULL,
-                "auto_scored_lower"	INTEGER NOT NULL,
-                "auto_scored_upper"	INTEGER NOT NULL,
-                "auto_shots"	INTEGER NOT NULL,
-                "teleop_scored_lower"	INTEGER NOT NULL,
-                "teleop_scored_upper"	INTEGER NOT NULL,
-                "teleop_shots"	INTEGER NOT NULL,
-                "pins"	INTEGER NOT NULL,
-                "times_pinned"	INTEGER NOT NULL,
-                "penalties"	INTEGER NOT NULL,
-                "performance" INTEGER NOT NULL,
-                "red_score" INTEGER NOT NULL,
-                "blue_score" INTEGER NOT NULL,
-                "climb" INTEGER NOT NULL,
-                "comment"	TEXT NOT NULL
-            );"#,
-        )
-        .await
-        .expect("Failed creating match responses table!");
-        
-        conn.execute(
-            r#"CREATE TABLE "pit_responses" (
-                "timestamp"	INTEGER NOT NULL,
-                "uuid" INTEGER NOT NULL,
-                "team"	INTEGER NOT NULL,
-                "team_name"	TEXT NOT NULL,
-                "weight"	INTEGER NOT NULL,
-                "drivetrain"    TEXT NOT NULL,
-                "size_x"	INTEGER NOT NULL,
-                "size_y"	INTEGER NOT NULL,
-                "size_z"	INTEGER NOT NULL,
-                "can_shoot_auto_upper"	INTEGER NOT NULL,
-                "can_shoot_auto_lower"	INTEGER NOT NULL,
-                "can_shoot_teleop_upper"	INTEGER NOT NULL,
-                "can_shoot_teleop_lower"	INTEGER NOT NULL,
-                "climb"	INTEGER NOT NULL,
-                "build_quality"	INTEGER NOT NULL,
-                "confidence"	INTEGER NOT NULL,
-                "driver_team"	INTEGER NOT NULL,
-                "comment"	TEXT NOT NULL,
-                "image"	BLOB
-            );"#,
-        )
-        .await
-        .expect("Failed creating pit responses table!");
-
-        conn.execute(r#"CREATE TABLE "team_details" (
-            "team"	INTEGER NOT NULL UNIQUE,
-            "matches"	INTEGER NOT NULL,
-            "taxi"	INTEGER NOT NULL,
-            "taxi_true"	INTEGER NOT NULL,
-            "preload"	INTEGER NOT NULL,
-            "auto_shoot"	INTEGER NOT NULL,
-            "auto_shoot_true"	INTEGER NOT NULL,
-            "auto_upper_accum"	INTEGER NOT NULL DEFAULT 0,
-            "auto_lower_accum"	INTEGER NOT NULL DEFAULT 0,
-            "shots_accum"	INTEGER NOT NULL DEFAULT 0,
-            "shots_upper_accum"	INTEGER NOT NULL DEFAULT 0,
-            "shots_lower_accum"	INTEGER NOT NULL DEFAULT 0,
-            "climb"	INTEGER NOT NULL DEFAULT 0,
-            "stated_climb"	INTEGER NOT NULL DEFAULT 0,
-            "score_accum"	INTEGER NOT NULL DEFAULT 0,
-            PRIMARY KEY("team")
-        )"#).await.expect("Failed creating team details table!");
-
-        conn.execute(r#"CREATE TABLE "images" (
-            "team"	INTEGER NOT NULL UNIQUE,
-            "img"	BLOB,
-            PRIMARY KEY("team")
-        )"#).await.expect("Failed creating images table!");
-
-        conn.close();
-
-        println!("Finished creating new db {}", DB_FILE);
-    } else {
-        drop(conn);
+            .expect("Failed migrating db!");
+        for migration in applied {
+            println!("Applied migration {}: {}", migration.version, migration.name);
+        }
     }
 
+    conn.close().await.expect("Failed closing db!");
+
     println!("Starting specialscout v{}...", env!("CARGO_PKG_VERSION"));
 
-    let pool = create_pool().await;
+    let pool = create_pool(&config).await;
+    let images = ImageStore::open(&config.image_dir).expect("Could not create image directory!");
+    let bind = config.bind.clone();
 
     HttpServer::new(move || {
         App::new()
+            .wrap(Logger::default())
+            // malformed bodies and parameters get the same JSON errors as everything else
+            .app_data(web::JsonConfig::default().error_handler(|e, _| {
+                ApiError::Invalid(e.to_string()).into()
+            }))
+            .app_data(web::QueryConfig::default().error_handler(|e, _| {
+                ApiError::Invalid(e.to_string()).into()
+            }))
+            .app_data(web::PathConfig::default().error_handler(|e, _| {
+                ApiError::NotFound(e.to_string()).into()
+            }))
             .service(dump_responses)
+            .service(dump_responses_mass)
+            .service(routes::teams::get_teams)
+            .service(routes::teams::get_team)
+            .service(routes::teams::get_team_pit)
+            .service(routes::teams::get_team_image)
+            .service(routes::teams::get_team_images)
+            .service(routes::teams::upload_team_images)
+            .service(routes::teams::get_team_trend)
+            .service(routes::responses::get_match_responses)
+            .service(routes::responses::patch_match_response)
+            .service(routes::responses::delete_match_response)
+            .service(routes::responses::patch_pit_response)
+            .service(routes::responses::delete_pit_response)
+            .service(routes::events::get_active_event)
+            .service(routes::events::get_opr)
+            .service(routes::events::put_schedule)
+            .service(routes::events::get_schedule)
+            .service(routes::events::generate_assignments)
+            .service(routes::events::get_assignments)
+            .service(routes::events::get_coverage)
+            .service(routes::events::get_reliability)
+            .service(routes::events::get_picklist)
+            .service(routes::events::put_picklist)
+            .service(routes::events::get_ranking_points)
+            .service(routes::events::get_rankings)
+            .service(routes::events::get_discrepancies)
+            .service(routes::export::export_match_responses)
+            .service(routes::export::export_pit_responses)
+            .service(routes::export::export_team_details)
+            .service(routes::images::get_image)
+            .service(routes::images::get_thumbnail)
+            .service(routes::predict::predict)
+            .service(routes::scouts::register_scout)
+            .service(routes::scouts::reissue_token)
+            .service(routes::scouts::get_scouts)
+            .service(routes::scouts::get_scout)
+            .service(routes::scouts::get_assignment)
+            .service(routes::admin::rebuild)
+            .service(routes::admin::consistency)
+            .service(routes::admin::audit_log)
             .route("/heartbeat", web::get().to(heartbeat))
-            .data(AppState { db: pool.clone() })
+            .route("/game", web::get().to(rules))
+            .data(AppState {
+                db: pool.clone(),
+                game: config.game(),
+                images: images.clone(),
+                config: config.clone(),
+            })
     })
-    .bind(IP)?
+    .bind(bind)?
     .run()
     .await
 }
 
-async fn create_pool() -> SqlitePool {
-    SqlitePool::connect(&format!("sqlite:{}", DB_FILE))
+async fn create_pool(config: &Config) -> SqlitePool {
+    SqlitePoolOptions::new()
+        .max_connections(config.pool_size)
+        .connect_with(SqliteConnectOptions::new().filename(&config.database))
         .await
         .expect("Failed to create pool!")
 }
@@ -0,0 +1,585 @@
+//! Forward-only schema migrations.
+//!
+//! Each migration has a version number and is applied at most once, tracked in the
+//! `schema_version` table. Databases created before versioning existed (including ones
+//! that have drifted from the old inline `CREATE TABLE` block) start at version 0, so every
+//! step here has to be safe to run against a table that may already be partly there.
+
+use sqlx::{query, query_scalar, Connection, Executor, SqliteConnection};
+
+pub enum Step {
+    Sql(&'static str),
+    /// Adds a column unless the table already has one by that name.
+    AddColumn {
+        table: &'static str,
+        column: &'static str,
+        definition: &'static str,
+    },
+}
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub steps: &'static [Step],
+}
+
+pub static MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial schema",
+        steps: &[
+            Step::Sql(
+                r#"CREATE TABLE IF NOT EXISTS "uuids" (
+                "uuid"	INTEGER NOT NULL UNIQUE,
+                "name"	TEXT,
+                "team_number"	INTEGER,
+                PRIMARY KEY("uuid")
+            )"#,
+            ),
+            Step::Sql(
+                r#"CREATE TABLE IF NOT EXISTS "teams" (
+                "team_number"	INTEGER NOT NULL,
+                "matches_played"	INTEGER NOT NULL DEFAULT 0,
+                "scouts"	INTEGER DEFAULT 0,
+                "responses"	INTEGER DEFAULT 0,
+                PRIMARY KEY("team_number")
+            )"#,
+            ),
+            Step::Sql(
+                r#"CREATE TABLE IF NOT EXISTS "matches" (
+                "event"	TEXT NOT NULL DEFAULT 'Unknown',
+                "match_number"	INTEGER,
+                "group"	TEXT,
+                "teams"	TEXT,
+                PRIMARY KEY("event")
+            )"#,
+            ),
+            Step::Sql(
+                r#"CREATE TABLE IF NOT EXISTS "match_responses" (
+                "timestamp"	INTEGER NOT NULL,
+                "uuid" INTEGER NOT NULL,
+                "event"	TEXT NOT NULL,
+                "team_number" INTEGER NOT NULL,
+                "match_number"	INTEGER NOT NULL,
+                "did_preload"	INTEGER NOT NULL,
+                "did_taxi"	INTEGER NOT NULL,
+                "got_field_cargo"	INTEGER NOT NULL,
+                "auto_scored_lower"	INTEGER NOT NULL,
+                "auto_scored_upper"	INTEGER NOT NULL,
+                "auto_shots"	INTEGER NOT NULL,
+                "teleop_scored_lower"	INTEGER NOT NULL,
+                "teleop_scored_upper"	INTEGER NOT NULL,
+                "teleop_shots"	INTEGER NOT NULL,
+                "pins"	INTEGER NOT NULL,
+                "times_pinned"	INTEGER NOT NULL,
+                "penalties"	INTEGER NOT NULL,
+                "performance" INTEGER NOT NULL,
+                "red_score" INTEGER NOT NULL,
+                "blue_score" INTEGER NOT NULL,
+                "climb" INTEGER NOT NULL,
+                "comment"	TEXT NOT NULL
+            )"#,
+            ),
+            Step::Sql(
+                r#"CREATE TABLE IF NOT EXISTS "pit_responses" (
+                "timestamp"	INTEGER NOT NULL,
+                "uuid" INTEGER NOT NULL,
+                "team"	INTEGER NOT NULL,
+                "team_name"	TEXT NOT NULL,
+                "weight"	INTEGER NOT NULL,
+                "size_x"	INTEGER NOT NULL,
+                "size_y"	INTEGER NOT NULL,
+                "size_z"	INTEGER NOT NULL,
+                "can_shoot_auto_upper"	INTEGER NOT NULL,
+                "can_shoot_auto_lower"	INTEGER NOT NULL,
+                "can_shoot_teleop_upper"	INTEGER NOT NULL,
+                "can_shoot_teleop_lower"	INTEGER NOT NULL,
+                "climb"	INTEGER NOT NULL,
+                "build_quality"	INTEGER NOT NULL,
+                "confidence"	INTEGER NOT NULL,
+                "driver_team"	INTEGER NOT NULL,
+                "comment"	TEXT NOT NULL,
+                "image"	BLOB
+            )"#,
+            ),
+            Step::Sql(
+                r#"CREATE TABLE IF NOT EXISTS "team_details" (
+                "team"	INTEGER NOT NULL UNIQUE,
+                "matches"	INTEGER NOT NULL,
+                "taxi"	INTEGER NOT NULL,
+                "taxi_true"	INTEGER NOT NULL,
+                "preload"	INTEGER NOT NULL,
+                "auto_shoot"	INTEGER NOT NULL,
+                "auto_shoot_true"	INTEGER NOT NULL,
+                "auto_upper_accum"	INTEGER NOT NULL DEFAULT 0,
+                "auto_lower_accum"	INTEGER NOT NULL DEFAULT 0,
+                "shots_accum"	INTEGER NOT NULL DEFAULT 0,
+                "shots_upper_accum"	INTEGER NOT NULL DEFAULT 0,
+                "shots_lower_accum"	INTEGER NOT NULL DEFAULT 0,
+                "climb"	INTEGER NOT NULL DEFAULT 0,
+                "stated_climb"	INTEGER NOT NULL DEFAULT 0,
+                "score_accum"	INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY("team")
+            )"#,
+            ),
+            Step::Sql(
+                r#"CREATE TABLE IF NOT EXISTS "images" (
+                "team"	INTEGER NOT NULL UNIQUE,
+                "img"	BLOB,
+                PRIMARY KEY("team")
+            )"#,
+            ),
+        ],
+    },
+    Migration {
+        version: 2,
+        name: "human player shots on match responses",
+        steps: &[
+            Step::AddColumn {
+                table: "match_responses",
+                column: "did_hp_shot",
+                definition: "INTEGER NOT NULL DEFAULT 0",
+            },
+            Step::AddColumn {
+                table: "match_responses",
+                column: "did_hp_sink",
+                definition: "INTEGER NOT NULL DEFAULT 0",
+            },
+        ],
+    },
+    Migration {
+        version: 3,
+        name: "drivetrain on pit responses",
+        steps: &[Step::AddColumn {
+            table: "pit_responses",
+            column: "drivetrain",
+            definition: "TEXT NOT NULL DEFAULT ''",
+        }],
+    },
+    Migration {
+        version: 4,
+        name: "alliance on match responses",
+        steps: &[Step::AddColumn {
+            table: "match_responses",
+            column: "alliance",
+            definition: "TEXT",
+        }],
+    },
+    Migration {
+        version: 5,
+        name: "client submission ids",
+        steps: &[
+            Step::AddColumn {
+                table: "match_responses",
+                column: "submission_id",
+                definition: "TEXT",
+            },
+            Step::AddColumn {
+                table: "pit_responses",
+                column: "submission_id",
+                definition: "TEXT",
+            },
+            Step::Sql(
+                r#"CREATE UNIQUE INDEX IF NOT EXISTS "match_responses_submission_id"
+                ON "match_responses" ("submission_id")"#,
+            ),
+            Step::Sql(
+                r#"CREATE UNIQUE INDEX IF NOT EXISTS "pit_responses_submission_id"
+                ON "pit_responses" ("submission_id")"#,
+            ),
+        ],
+    },
+    Migration {
+        version: 6,
+        name: "response ids and audit log",
+        // SQLite can't add a primary key to an existing table, so both response tables are
+        // rebuilt with an `id` column and their rows copied across in insertion order
+        steps: &[
+            Step::Sql(
+                r#"CREATE TABLE "match_responses_new" (
+                "id"	INTEGER NOT NULL,
+                "timestamp"	INTEGER NOT NULL,
+                "uuid" INTEGER NOT NULL,
+                "event"	TEXT NOT NULL,
+                "team_number" INTEGER NOT NULL,
+                "match_number"	INTEGER NOT NULL,
+                "did_preload"	INTEGER NOT NULL,
+                "did_taxi"	INTEGER NOT NULL,
+                "got_field_cargo"	INTEGER NOT NULL,
+                "did_hp_shot"   INTEGER NOT NULL,
+                "did_hp_sink"   INTEGER NOT NULL,
+                "auto_scored_lower"	INTEGER NOT NULL,
+                "auto_scored_upper"	INTEGER NOT NULL,
+                "auto_shots"	INTEGER NOT NULL,
+                "teleop_scored_lower"	INTEGER NOT NULL,
+                "teleop_scored_upper"	INTEGER NOT NULL,
+                "teleop_shots"	INTEGER NOT NULL,
+                "pins"	INTEGER NOT NULL,
+                "times_pinned"	INTEGER NOT NULL,
+                "penalties"	INTEGER NOT NULL,
+                "performance" INTEGER NOT NULL,
+                "red_score" INTEGER NOT NULL,
+                "blue_score" INTEGER NOT NULL,
+                "climb" INTEGER NOT NULL,
+                "comment"	TEXT NOT NULL,
+                "alliance"	TEXT,
+                "submission_id"	TEXT,
+                PRIMARY KEY("id" AUTOINCREMENT)
+            )"#,
+            ),
+            Step::Sql(
+                r#"INSERT INTO "match_responses_new" (
+                    timestamp, uuid, event, team_number, match_number, did_preload, did_taxi,
+                    got_field_cargo, did_hp_shot, did_hp_sink, auto_scored_lower, auto_scored_upper,
+                    auto_shots, teleop_scored_lower, teleop_scored_upper, teleop_shots, pins,
+                    times_pinned, penalties, performance, red_score, blue_score, climb, comment,
+                    alliance, submission_id
+                ) SELECT
+                    timestamp, uuid, event, team_number, match_number, did_preload, did_taxi,
+                    got_field_cargo, did_hp_shot, did_hp_sink, auto_scored_lower, auto_scored_upper,
+                    auto_shots, teleop_scored_lower, teleop_scored_upper, teleop_shots, pins,
+                    times_pinned, penalties, performance, red_score, blue_score, climb, comment,
+                    alliance, submission_id
+                FROM "match_responses" ORDER BY rowid"#,
+            ),
+            Step::Sql(r#"DROP TABLE "match_responses""#),
+            Step::Sql(r#"ALTER TABLE "match_responses_new" RENAME TO "match_responses""#),
+            Step::Sql(
+                r#"CREATE UNIQUE INDEX IF NOT EXISTS "match_responses_submission_id"
+                ON "match_responses" ("submission_id")"#,
+            ),
+            Step::Sql(
+                r#"CREATE TABLE "pit_responses_new" (
+                "id"	INTEGER NOT NULL,
+                "timestamp"	INTEGER NOT NULL,
+                "uuid" INTEGER NOT NULL,
+                "team"	INTEGER NOT NULL,
+                "team_name"	TEXT NOT NULL,
+                "weight"	INTEGER NOT NULL,
+                "drivetrain"    TEXT NOT NULL,
+                "size_x"	INTEGER NOT NULL,
+                "size_y"	INTEGER NOT NULL,
+                "size_z"	INTEGER NOT NULL,
+                "can_shoot_auto_upper"	INTEGER NOT NULL,
+                "can_shoot_auto_lower"	INTEGER NOT NULL,
+                "can_shoot_teleop_upper"	INTEGER NOT NULL,
+                "can_shoot_teleop_lower"	INTEGER NOT NULL,
+                "climb"	INTEGER NOT NULL,
+                "build_quality"	INTEGER NOT NULL,
+                "confidence"	INTEGER NOT NULL,
+                "driver_team"	INTEGER NOT NULL,
+                "comment"	TEXT NOT NULL,
+                "image"	BLOB,
+                "submission_id"	TEXT,
+                PRIMARY KEY("id" AUTOINCREMENT)
+            )"#,
+            ),
+            Step::Sql(
+                r#"INSERT INTO "pit_responses_new" (
+                    timestamp, uuid, team, team_name, weight, drivetrain, size_x, size_y, size_z,
+                    can_shoot_auto_upper, can_shoot_auto_lower, can_shoot_teleop_upper,
+                    can_shoot_teleop_lower, climb, build_quality, confidence, driver_team, comment,
+                    image, submission_id
+                ) SELECT
+                    timestamp, uuid, team, team_name, weight, drivetrain, size_x, size_y, size_z,
+                    can_shoot_auto_upper, can_shoot_auto_lower, can_shoot_teleop_upper,
+                    can_shoot_teleop_lower, climb, build_quality, confidence, driver_team, comment,
+                    image, submission_id
+                FROM "pit_responses" ORDER BY rowid"#,
+            ),
+            Step::Sql(r#"DROP TABLE "pit_responses""#),
+            Step::Sql(r#"ALTER TABLE "pit_responses_new" RENAME TO "pit_responses""#),
+            Step::Sql(
+                r#"CREATE UNIQUE INDEX IF NOT EXISTS "pit_responses_submission_id"
+                ON "pit_responses" ("submission_id")"#,
+            ),
+            Step::Sql(
+                r#"CREATE TABLE IF NOT EXISTS "audit_log" (
+                "id"	INTEGER NOT NULL,
+                "timestamp"	INTEGER NOT NULL,
+                "changed_by"	TEXT NOT NULL,
+                "action"	TEXT NOT NULL,
+                "table_name"	TEXT NOT NULL,
+                "row_id"	INTEGER NOT NULL,
+                "before"	TEXT,
+                "after"	TEXT,
+                PRIMARY KEY("id" AUTOINCREMENT)
+            )"#,
+            ),
+        ],
+    },
+    Migration {
+        version: 7,
+        name: "scout tokens",
+        steps: &[
+            Step::AddColumn {
+                table: "uuids",
+                column: "token_hash",
+                definition: "TEXT",
+            },
+            Step::Sql(
+                r#"CREATE UNIQUE INDEX IF NOT EXISTS "uuids_token_hash"
+                ON "uuids" ("token_hash")"#,
+            ),
+        ],
+    },
+    Migration {
+        version: 8,
+        name: "match schedule and scout assignments",
+        // `matches` was keyed on event alone, so it could only ever hold one match per event
+        steps: &[
+            Step::Sql(
+                r#"CREATE TABLE "matches_new" (
+                "event"	TEXT NOT NULL DEFAULT 'Unknown',
+                "match_number"	INTEGER NOT NULL,
+                "group"	TEXT NOT NULL DEFAULT 'qm',
+                PRIMARY KEY("event", "match_number")
+            )"#,
+            ),
+            Step::Sql(
+                r#"INSERT OR IGNORE INTO "matches_new" ("event", "match_number", "group")
+                SELECT "event", "match_number", COALESCE("group", 'qm') FROM "matches"
+                WHERE "match_number" IS NOT NULL"#,
+            ),
+            Step::Sql(r#"DROP TABLE "matches""#),
+            Step::Sql(r#"ALTER TABLE "matches_new" RENAME TO "matches""#),
+            Step::Sql(
+                r#"CREATE TABLE IF NOT EXISTS "match_teams" (
+                "event"	TEXT NOT NULL,
+                "match_number"	INTEGER NOT NULL,
+                "alliance"	TEXT NOT NULL,
+                "station"	INTEGER NOT NULL,
+                "team_number"	INTEGER NOT NULL,
+                PRIMARY KEY("event", "match_number", "alliance", "station")
+            )"#,
+            ),
+            Step::Sql(
+                r#"CREATE TABLE IF NOT EXISTS "assignments" (
+                "event"	TEXT NOT NULL,
+                "match_number"	INTEGER NOT NULL,
+                "uuid"	INTEGER NOT NULL,
+                "alliance"	TEXT NOT NULL,
+                "station"	INTEGER NOT NULL,
+                "team_number"	INTEGER NOT NULL,
+                PRIMARY KEY("event", "match_number", "uuid")
+            )"#,
+            ),
+        ],
+    },
+    Migration {
+        version: 9,
+        name: "pick lists",
+        steps: &[
+            Step::Sql(
+                r#"CREATE TABLE IF NOT EXISTS "picklists" (
+                "event"	TEXT NOT NULL,
+                "version"	INTEGER NOT NULL,
+                "updated_at"	INTEGER NOT NULL,
+                "updated_by"	TEXT NOT NULL,
+                PRIMARY KEY("event")
+            )"#,
+            ),
+            Step::Sql(
+                r#"CREATE TABLE IF NOT EXISTS "picklist_entries" (
+                "event"	TEXT NOT NULL,
+                "team_number"	INTEGER NOT NULL,
+                "rank"	INTEGER NOT NULL,
+                "struck"	INTEGER NOT NULL DEFAULT 0,
+                "note"	TEXT NOT NULL DEFAULT '',
+                PRIMARY KEY("event", "team_number")
+            )"#,
+            ),
+        ],
+    },
+    Migration {
+        version: 10,
+        name: "image store",
+        // photos now live on disk; `images` and `pit_responses.image` are only read for old rows
+        steps: &[
+            Step::Sql(
+                r#"CREATE TABLE IF NOT EXISTS "team_images" (
+                "id"	TEXT NOT NULL,
+                "team"	INTEGER NOT NULL,
+                "content_type"	TEXT NOT NULL,
+                "size"	INTEGER NOT NULL,
+                "uploaded_at"	INTEGER NOT NULL,
+                "uploaded_by"	INTEGER,
+                PRIMARY KEY("team", "id")
+            )"#,
+            ),
+            Step::AddColumn {
+                table: "pit_responses",
+                column: "image_ids",
+                definition: "TEXT NOT NULL DEFAULT '[]'",
+            },
+        ],
+    },
+    Migration {
+        version: 11,
+        name: "wins in team details",
+        // existing rows read 0 until the next rebuild, which the consistency check will flag
+        steps: &[Step::AddColumn {
+            table: "team_details",
+            column: "matches_won",
+            definition: "INTEGER NOT NULL DEFAULT 0",
+        }],
+    },
+];
+
+/// The version a fully migrated database ends up at.
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+async fn ensure_version_table(conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+    conn.execute(
+        r#"CREATE TABLE IF NOT EXISTS "schema_version" (
+            "version"	INTEGER NOT NULL,
+            "name"	TEXT NOT NULL,
+            "applied_at"	INTEGER NOT NULL,
+            PRIMARY KEY("version")
+        )"#,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// The highest migration applied to this database, or 0 if none have been.
+pub async fn current_version(conn: &mut SqliteConnection) -> Result<i64, sqlx::Error> {
+    ensure_version_table(conn).await?;
+
+    query_scalar::<_, Option<i64>>(r#"SELECT MAX(version) FROM schema_version"#)
+        .fetch_one(conn)
+        .await
+        .map(|v| v.unwrap_or(0))
+}
+
+async fn has_column(
+    conn: &mut SqliteConnection,
+    table: &str,
+    column: &str,
+) -> Result<bool, sqlx::Error> {
+    let count: i64 = query_scalar("SELECT COUNT(*) FROM pragma_table_info(?) WHERE name = ?")
+        .bind(table)
+        .bind(column)
+        .fetch_one(conn)
+        .await?;
+
+    Ok(count > 0)
+}
+
+/// Applies every pending migration in order, each in its own transaction.
+/// Returns the migrations that were applied.
+pub async fn migrate(conn: &mut SqliteConnection) -> Result<Vec<&'static Migration>, sqlx::Error> {
+    let current = current_version(conn).await?;
+    let mut applied = Vec::new();
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let mut tx = conn.begin().await?;
+
+        for step in migration.steps {
+            match step {
+                Step::Sql(sql) => {
+                    tx.execute(*sql).await?;
+                }
+                Step::AddColumn {
+                    table,
+                    column,
+                    definition,
+                } => {
+                    if !has_column(&mut tx, table, column).await? {
+                        tx.execute(
+                            format!(r#"ALTER TABLE "{}" ADD COLUMN "{}" {}"#, table, column, definition)
+                                .as_str(),
+                        )
+                        .await?;
+                    }
+                }
+            }
+        }
+
+        query(r#"INSERT INTO schema_version VALUES (?, ?, strftime('%s', 'now'))"#)
+            .bind(migration.version)
+            .bind(migration.name)
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+        applied.push(migration);
+    }
+
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf, process};
+
+    use futures::executor::block_on;
+    use sqlx::{sqlite::SqliteConnectOptions, ConnectOptions};
+
+    use super::*;
+
+    /// A scratch database file, removed when dropped.
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new(name: &str) -> Self {
+            let file = format!("specialscout-{}-{}.sqlite", name, process::id());
+            let path = std::env::temp_dir().join(file);
+            let _ = fs::remove_file(&path);
+            Scratch(path)
+        }
+
+        async fn connect(&self) -> SqliteConnection {
+            SqliteConnectOptions::new()
+                .filename(&self.0)
+                .create_if_missing(true)
+                .connect()
+                .await
+                .unwrap()
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    async fn assert_fully_migrated(conn: &mut SqliteConnection) {
+        migrate(conn).await.unwrap();
+        assert_eq!(current_version(conn).await.unwrap(), latest_version());
+        let has_images = has_column(conn, "pit_responses", "image_ids")
+            .await
+            .unwrap();
+        assert!(has_images);
+
+        // running again has nothing left to do
+        assert!(migrate(conn).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn migrates_the_baseline_database() {
+        let scratch = Scratch::new("baseline");
+        let baseline = concat!(env!("CARGO_MANIFEST_DIR"), "/db.sqlite");
+        fs::copy(baseline, &scratch.0).unwrap();
+
+        block_on(async {
+            let mut conn = scratch.connect().await;
+            assert_eq!(current_version(&mut conn).await.unwrap(), 0);
+            assert_fully_migrated(&mut conn).await;
+        });
+    }
+
+    #[test]
+    fn migrates_an_empty_database() {
+        let scratch = Scratch::new("empty");
+
+        block_on(async {
+            let mut conn = scratch.connect().await;
+            assert_fully_migrated(&mut conn).await;
+        });
+    }
+}
@@ -0,0 +1,387 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+};
+
+use serde::Serialize;
+
+use crate::{game::Alliance, schedule::ScheduledMatch};
+
+/// Robots on a full alliance. Fewer known teams would credit the whole score to the ones
+/// that were scouted.
+pub const ALLIANCE_SIZE: usize = 3;
+
+/// One alliance's side of a played match.
+#[derive(Debug, Clone)]
+pub struct AllianceResult {
+    pub teams: Vec<u32>,
+    pub score: f64,
+    pub opponent_score: f64,
+}
+
+/// What one scout recorded about a match: the robot they watched, its alliance if known,
+/// and the final score.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ScoreReport {
+    pub match_number: i16,
+    pub team_number: u32,
+    pub alliance: Option<Alliance>,
+    pub red_score: i32,
+    pub blue_score: i32,
+}
+
+#[derive(Default)]
+struct PlayedMatch {
+    red: Vec<u32>,
+    blue: Vec<u32>,
+    red_scores: Vec<i32>,
+    blue_scores: Vec<i32>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Rating {
+    pub team: u32,
+    pub opr: f64,
+    pub dpr: f64,
+    pub ccwm: f64,
+}
+
+/// Most commonly reported value, since scouts occasionally mistype the final score.
+pub fn mode(values: &[i32]) -> i32 {
+    let mut counts: HashMap<i32, usize> = HashMap::new();
+    for &v in values {
+        *counts.entry(v).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .max_by_key(|&(v, count)| (count, v))
+        .map(|(v, _)| v)
+        .unwrap_or(0)
+}
+
+/// Turns score reports into one result per alliance per scouted match.
+///
+/// Alliances are taken from `schedule` when it has the match, so a robot nobody scouted
+/// still shares its alliance's score. Unscheduled matches fall back to the alliances the
+/// scouts recorded, and alliances with fewer than [`ALLIANCE_SIZE`] known teams are left
+/// out.
+pub fn alliance_results(
+    reports: &[ScoreReport],
+    schedule: &[ScheduledMatch],
+) -> Vec<AllianceResult> {
+    let mut matches: BTreeMap<i16, PlayedMatch> = BTreeMap::new();
+    for report in reports {
+        let played = matches.entry(report.match_number).or_default();
+        if let Some(alliance) = report.alliance {
+            let teams = match alliance {
+                Alliance::Red => &mut played.red,
+                Alliance::Blue => &mut played.blue,
+            };
+            if !teams.contains(&report.team_number) {
+                teams.push(report.team_number);
+            }
+        }
+        played.red_scores.push(report.red_score);
+        played.blue_scores.push(report.blue_score);
+    }
+
+    let mut results = Vec::new();
+    for (match_number, mut played) in matches {
+        // a scheduled alliance is complete however many teams it has
+        let min_teams = match schedule.iter().find(|m| m.match_number == match_number) {
+            Some(scheduled) => {
+                played.red = scheduled.red.clone();
+                played.blue = scheduled.blue.clone();
+                1
+            }
+            None => ALLIANCE_SIZE,
+        };
+
+        let red_score = mode(&played.red_scores) as f64;
+        let blue_score = mode(&played.blue_scores) as f64;
+
+        for (teams, score, opponent_score) in [
+            (played.red, red_score, blue_score),
+            (played.blue, blue_score, red_score),
+        ] {
+            if teams.len() >= min_teams {
+                results.push(AllianceResult {
+                    teams,
+                    score,
+                    opponent_score,
+                });
+            }
+        }
+    }
+
+    results
+}
+
+/// Solves for OPR and DPR over every alliance result with least squares.
+///
+/// Each alliance contributes one equation: the sum of its teams' ratings equals its score
+/// (OPR) or its opponent's score (DPR). CCWM is the difference of the two.
+pub fn solve(results: &[AllianceResult]) -> Vec<Rating> {
+    let mut teams: Vec<u32> = results
+        .iter()
+        .flat_map(|r| r.teams.iter().copied())
+        .collect();
+    teams.sort_unstable();
+    teams.dedup();
+
+    let index: HashMap<u32, usize> = teams.iter().enumerate().map(|(i, &t)| (t, i)).collect();
+    let n = teams.len();
+
+    // normal equations: (AᵀA) x = Aᵀb, built directly since A is a 0/1 membership matrix
+    let mut ata = vec![vec![0.0; n]; n];
+    let mut atb_for = vec![0.0; n];
+    let mut atb_against = vec![0.0; n];
+
+    for result in results {
+        let members: Vec<usize> = result.teams.iter().map(|t| index[t]).collect();
+        for &i in &members {
+            for &j in &members {
+                ata[i][j] += 1.0;
+            }
+            atb_for[i] += result.score;
+            atb_against[i] += result.opponent_score;
+        }
+    }
+
+    let opr = solve_linear(ata.clone(), atb_for);
+    let dpr = solve_linear(ata, atb_against);
+
+    teams
+        .into_iter()
+        .enumerate()
+        .map(|(i, team)| Rating {
+            team,
+            opr: opr[i],
+            dpr: dpr[i],
+            ccwm: opr[i] - dpr[i],
+        })
+        .collect()
+}
+
+/// Gaussian elimination with partial pivoting. Variables that the data can't pin down
+/// (a singular system, e.g. two teams that only ever played together) are left at 0.
+fn solve_linear(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Vec<f64> {
+    const EPSILON: f64 = 1e-9;
+    let n = b.len();
+    let mut pivots: Vec<Option<usize>> = vec![None; n];
+    let mut row = 0;
+
+    for col in 0..n {
+        if row == n {
+            break;
+        }
+
+        let best = (row..n)
+            .max_by(|&x, &y| {
+                a[x][col]
+                    .abs()
+                    .partial_cmp(&a[y][col].abs())
+                    .unwrap_or(Ordering::Equal)
+            })
+            .unwrap();
+        if a[best][col].abs() < EPSILON {
+            continue;
+        }
+
+        a.swap(row, best);
+        b.swap(row, best);
+
+        let pivot_row = a[row].clone();
+        let pivot_b = b[row];
+        for other in 0..n {
+            if other == row || a[other][col] == 0.0 {
+                continue;
+            }
+            let factor = a[other][col] / pivot_row[col];
+            for (cell, pivot) in a[other][col..].iter_mut().zip(&pivot_row[col..]) {
+                *cell -= factor * pivot;
+            }
+            b[other] -= factor * pivot_b;
+        }
+
+        pivots[col] = Some(row);
+        row += 1;
+    }
+
+    pivots
+        .into_iter()
+        .enumerate()
+        .map(|(col, pivot)| match pivot {
+            Some(row) => b[row] / a[row][col],
+            None => 0.0,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alliance(teams: &[u32], score: f64, opponent_score: f64) -> AllianceResult {
+        AllianceResult {
+            teams: teams.to_vec(),
+            score,
+            opponent_score,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn solves_exactly_determined_event() {
+        // a + b = 10, b + c = 14, a + c = 12, so a = 4, b = 6, c = 8
+        let ratings = solve(&[
+            alliance(&[1, 2], 10.0, 5.0),
+            alliance(&[2, 3], 14.0, 7.0),
+            alliance(&[1, 3], 12.0, 6.0),
+        ]);
+
+        let teams: Vec<u32> = ratings.iter().map(|r| r.team).collect();
+        assert_eq!(teams, [1, 2, 3]);
+        for (rating, (opr, dpr)) in ratings.iter().zip([(4.0, 2.0), (6.0, 3.0), (8.0, 4.0)]) {
+            assert_close(rating.opr, opr);
+            assert_close(rating.dpr, dpr);
+            assert_close(rating.ccwm, opr - dpr);
+        }
+    }
+
+    #[test]
+    fn least_squares_splits_inconsistent_scores() {
+        // one team scoring 10 and then 20 is best explained by 15
+        let ratings = solve(&[alliance(&[1], 10.0, 0.0), alliance(&[1], 20.0, 0.0)]);
+
+        assert_close(ratings[0].opr, 15.0);
+    }
+
+    #[test]
+    fn inseparable_teams_leave_one_at_zero() {
+        let ratings = solve(&[alliance(&[1, 2], 10.0, 4.0), alliance(&[1, 2], 10.0, 4.0)]);
+
+        for rating in &ratings {
+            assert!(rating.opr.is_finite() && rating.dpr.is_finite());
+        }
+        assert_close(ratings[0].opr + ratings[1].opr, 10.0);
+        assert_close(ratings[1].opr, 0.0);
+        assert_close(ratings[1].dpr, 0.0);
+    }
+
+    #[test]
+    fn singular_system_solves_the_determined_part() {
+        let x = solve_linear(vec![vec![2.0, 0.0], vec![0.0, 0.0]], vec![6.0, 0.0]);
+        assert_eq!(x, [3.0, 0.0]);
+    }
+
+    fn report(match_number: i16, team_number: u32, alliance: Alliance) -> ScoreReport {
+        // scores chosen so each team is worth its number
+        let (red_score, blue_score) = match match_number {
+            1 => (1 + 2 + 3, 4 + 5 + 6),
+            2 => (1 + 4 + 5, 2 + 3 + 6),
+            _ => (1 + 3 + 6, 2 + 4 + 5),
+        };
+        ScoreReport {
+            match_number,
+            team_number,
+            alliance: Some(alliance),
+            red_score,
+            blue_score,
+        }
+    }
+
+    fn event() -> (Vec<ScheduledMatch>, Vec<ScoreReport>) {
+        let schedule = vec![
+            ScheduledMatch {
+                match_number: 1,
+                red: vec![1, 2, 3],
+                blue: vec![4, 5, 6],
+            },
+            ScheduledMatch {
+                match_number: 2,
+                red: vec![1, 4, 5],
+                blue: vec![2, 3, 6],
+            },
+            ScheduledMatch {
+                match_number: 3,
+                red: vec![1, 3, 6],
+                blue: vec![2, 4, 5],
+            },
+        ];
+        let reports = schedule
+            .iter()
+            .flat_map(|m| {
+                let red = m
+                    .red
+                    .iter()
+                    .map(move |&t| report(m.match_number, t, Alliance::Red));
+                let blue = m
+                    .blue
+                    .iter()
+                    .map(move |&t| report(m.match_number, t, Alliance::Blue));
+                red.chain(blue)
+            })
+            .collect();
+        (schedule, reports)
+    }
+
+    #[test]
+    fn unscouted_robot_keeps_its_share_of_scheduled_alliance() {
+        let (schedule, reports) = event();
+        let full = solve(&alliance_results(&reports, &schedule));
+
+        // nobody watched team 3 in match 1
+        let partial: Vec<ScoreReport> = reports
+            .iter()
+            .filter(|r| !(r.match_number == 1 && r.team_number == 3))
+            .cloned()
+            .collect();
+        let ratings = solve(&alliance_results(&partial, &schedule));
+
+        assert_eq!(ratings.len(), 6);
+        for (with, without) in full.iter().zip(&ratings) {
+            assert_eq!(with.team, without.team);
+            assert_close(with.opr, without.opr);
+            assert_close(with.dpr, without.dpr);
+        }
+    }
+
+    #[test]
+    fn unscheduled_alliance_needs_every_team() {
+        let (_, reports) = event();
+        let partial: Vec<ScoreReport> = reports
+            .into_iter()
+            .filter(|r| !(r.match_number == 1 && r.team_number == 3))
+            .collect();
+
+        let results = alliance_results(&partial, &[]);
+        assert_eq!(results.len(), 5);
+        assert!(results.iter().all(|r| r.teams.len() == ALLIANCE_SIZE));
+        assert!(!results.iter().any(|r| r.teams == [1, 2]));
+    }
+
+    #[test]
+    fn scores_come_from_the_most_common_report() {
+        let (schedule, mut reports) = event();
+        reports[0].red_score = 60;
+
+        let results = alliance_results(&reports, &schedule);
+        assert_eq!((results[0].score, results[0].opponent_score), (6.0, 15.0));
+    }
+
+    #[test]
+    fn mode_prefers_most_common_then_highest() {
+        assert_eq!(mode(&[40, 42, 40]), 40);
+        assert_eq!(mode(&[40, 42]), 42);
+        assert_eq!(mode(&[]), 0);
+    }
+}
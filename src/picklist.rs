@@ -0,0 +1,162 @@
+//! Alliance selection pick lists, shared between every laptop in the stands.
+//!
+//! Each event has at most one list. It carries a version that every save must quote, so two
+//! strategists editing at once can't silently overwrite each other: the second save is
+//! refused and has to start again from the current list.
+
+use std::{cmp::Ordering, collections::HashSet};
+
+use serde::{Deserialize, Serialize};
+use sqlx::{query, query_as, SqliteConnection};
+
+use crate::{db::TeamDetails, game::DetailedTeam};
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Entry {
+    pub team_number: u32,
+    /// Already picked or ruled out.
+    #[serde(default)]
+    pub struck: bool,
+    #[serde(default)]
+    pub note: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PickList {
+    pub event: String,
+    /// 0 for a list that has never been saved.
+    pub version: i64,
+    pub updated_at: Option<i64>,
+    pub updated_by: Option<String>,
+    /// Best first.
+    pub teams: Vec<Entry>,
+}
+
+/// Orders teams for a fresh list: average estimated score first, then climb, then whether
+/// they can score in auto at all.
+pub fn seed(details: Vec<TeamDetails>) -> Vec<Entry> {
+    let mut teams: Vec<DetailedTeam> = details.into_iter().map(DetailedTeam::from).collect();
+
+    teams.sort_by(|a, b| {
+        b.avg_score
+            .partial_cmp(&a.avg_score)
+            .unwrap_or(Ordering::Equal)
+            .then(b.climb.cmp(&a.climb))
+            .then(b.auto_shoot.cmp(&a.auto_shoot))
+            .then(a.team.number().cmp(&b.team.number()))
+    });
+
+    teams
+        .into_iter()
+        .map(|t| Entry {
+            team_number: t.team.number() as u32,
+            struck: false,
+            note: String::new(),
+        })
+        .collect()
+}
+
+/// Rejects lists that name a team more than once.
+pub fn validate(teams: &[Entry]) -> Result<(), String> {
+    let mut seen = HashSet::new();
+    for entry in teams {
+        if !seen.insert(entry.team_number) {
+            return Err(format!("team {} is listed twice", entry.team_number));
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(sqlx::FromRow)]
+struct Header {
+    version: i64,
+    updated_at: i64,
+    updated_by: String,
+}
+
+/// The stored list for `event`, if one has been saved.
+pub async fn load(
+    conn: &mut SqliteConnection,
+    event: &str,
+) -> Result<Option<PickList>, sqlx::Error> {
+    let header = query_as::<_, Header>(
+        "SELECT version, updated_at, updated_by FROM picklists WHERE event = ?",
+    )
+    .bind(event)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let header = match header {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+
+    let teams = query_as::<_, Entry>(
+        "SELECT team_number, struck, note FROM picklist_entries WHERE event = ? ORDER BY rank",
+    )
+    .bind(event)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(Some(PickList {
+        event: event.to_string(),
+        version: header.version,
+        updated_at: Some(header.updated_at),
+        updated_by: Some(header.updated_by),
+        teams,
+    }))
+}
+
+/// Replaces the list for `event` if it is still at `version`, returning the new version.
+/// Returns `None` without changing anything if somebody else saved first.
+pub async fn store(
+    conn: &mut SqliteConnection,
+    event: &str,
+    version: i64,
+    updated_by: &str,
+    teams: &[Entry],
+) -> Result<Option<i64>, sqlx::Error> {
+    let claimed = if version == 0 {
+        query(
+            r#"INSERT OR IGNORE INTO picklists (event, version, updated_at, updated_by)
+                VALUES (?1, 1, strftime('%s', 'now'), ?2)"#,
+        )
+        .bind(event)
+        .bind(updated_by)
+        .execute(&mut *conn)
+        .await?
+    } else {
+        query(
+            r#"UPDATE picklists SET version = version + 1, updated_at = strftime('%s', 'now'),
+                updated_by = ?2 WHERE event = ?1 AND version = ?3"#,
+        )
+        .bind(event)
+        .bind(updated_by)
+        .bind(version)
+        .execute(&mut *conn)
+        .await?
+    };
+
+    if claimed.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    query("DELETE FROM picklist_entries WHERE event = ?")
+        .bind(event)
+        .execute(&mut *conn)
+        .await?;
+
+    for (rank, entry) in teams.iter().enumerate() {
+        query("INSERT INTO picklist_entries VALUES (?, ?, ?, ?, ?)")
+            .bind(event)
+            .bind(entry.team_number)
+            .bind(rank as i64 + 1)
+            .bind(entry.struck)
+            .bind(&entry.note)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    Ok(Some(version + 1))
+}
@@ -0,0 +1,314 @@
+//! Monte Carlo forecasts for matches that haven't been played yet.
+//!
+//! Each run draws one of every robot's observed matches at random, adds up the alliance
+//! scores and scores the result, bonus ranking points included. Robots nobody has scouted
+//! contribute nothing, so a prediction involving them is a lower bound for their alliance.
+
+use std::collections::BTreeMap;
+
+use rand::{seq::SliceRandom, Rng};
+use serde::Serialize;
+use sqlx::{query_as, SqliteConnection};
+
+use crate::{
+    consensus,
+    db::MatchResponse,
+    game::FormIngest,
+    season::{FieldValues, Game},
+};
+
+/// What one robot did in one observed match: its estimated score, plus every numeric form
+/// field for seasons whose bonus ranking points depend on them.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub score: i64,
+    values: Vec<(&'static str, i64)>,
+}
+
+impl Sample {
+    pub fn new(observation: &dyn FieldValues, game: &dyn Game) -> Self {
+        Sample {
+            score: game.score(observation),
+            values: game
+                .match_fields()
+                .iter()
+                .filter_map(|f| observation.value(f.name).map(|v| (f.name, v)))
+                .collect(),
+        }
+    }
+}
+
+impl FieldValues for Sample {
+    fn value(&self, field: &str) -> Option<i64> {
+        self.values
+            .iter()
+            .find(|(name, _)| *name == field)
+            .map(|(_, v)| *v)
+    }
+}
+
+/// Every observed match of one robot.
+#[derive(Debug, Clone)]
+pub struct TeamSamples {
+    pub team: u32,
+    pub samples: Vec<Sample>,
+}
+
+impl TeamSamples {
+    /// Reads a team's observed matches, merging double-scouted ones, optionally only from one
+    /// event.
+    pub async fn load(
+        conn: &mut SqliteConnection,
+        team: u32,
+        event: Option<&str>,
+        game: &dyn Game,
+    ) -> Result<Self, sqlx::Error> {
+        let responses = query_as::<_, MatchResponse>(
+            "SELECT * FROM match_responses WHERE team_number = ?1 AND (?2 IS NULL OR event = ?2)",
+        )
+        .bind(team)
+        .bind(event)
+        .fetch_all(conn)
+        .await?;
+
+        let samples = consensus::canonical(responses)
+            .into_iter()
+            .map(|r| Sample::new(&FormIngest::from(r), game))
+            .collect();
+
+        Ok(TeamSamples { team, samples })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AllianceForecast {
+    pub teams: Vec<u32>,
+    pub mean_score: f64,
+    /// 10th and 90th percentile of the simulated score.
+    pub low_score: i64,
+    pub high_score: i64,
+    pub win_probability: f64,
+    pub expected_rp: f64,
+    /// How often each ranking point total came up, as a fraction of runs.
+    pub rp_outcomes: BTreeMap<u8, f64>,
+    /// How often each bonus ranking point was earned, as a fraction of runs.
+    pub bonus_probability: BTreeMap<&'static str, f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Prediction {
+    pub red: AllianceForecast,
+    pub blue: AllianceForecast,
+    pub tie_probability: f64,
+    pub runs: usize,
+    /// Teams without any scouted matches.
+    pub unscouted: Vec<u32>,
+}
+
+/// One simulated alliance: a random observation per robot, and their total score.
+fn draw<'a>(alliance: &'a [TeamSamples], rng: &mut impl Rng) -> (Vec<&'a dyn FieldValues>, i64) {
+    let drawn: Vec<&Sample> = alliance
+        .iter()
+        .filter_map(|team| team.samples.choose(rng))
+        .collect();
+    let score = drawn.iter().map(|s| s.score).sum();
+
+    (
+        drawn.into_iter().map(|s| s as &dyn FieldValues).collect(),
+        score,
+    )
+}
+
+struct Tally {
+    scores: Vec<i64>,
+    wins: usize,
+    rp: BTreeMap<u8, usize>,
+    bonuses: BTreeMap<&'static str, usize>,
+}
+
+impl Tally {
+    fn new(runs: usize) -> Self {
+        Tally {
+            scores: Vec::with_capacity(runs),
+            wins: 0,
+            rp: BTreeMap::new(),
+            bonuses: BTreeMap::new(),
+        }
+    }
+
+    fn record(
+        &mut self,
+        robots: &[&dyn FieldValues],
+        score: i64,
+        opponent_score: i64,
+        game: &dyn Game,
+    ) {
+        let mut rp = game.result_rp(score, opponent_score);
+        for bonus in game.bonus_rp(robots) {
+            let earned = self.bonuses.entry(bonus.name).or_default();
+            if bonus.earned {
+                *earned += 1;
+                rp += 1;
+            }
+        }
+
+        self.scores.push(score);
+        self.wins += (score > opponent_score) as usize;
+        *self.rp.entry(rp).or_default() += 1;
+    }
+
+    fn forecast(mut self, alliance: &[TeamSamples]) -> AllianceForecast {
+        let runs = self.scores.len().max(1) as f64;
+        self.scores.sort_unstable();
+        let percentile = |p: usize| {
+            self.scores
+                .get(self.scores.len() * p / 100)
+                .copied()
+                .unwrap_or(0)
+        };
+
+        AllianceForecast {
+            teams: alliance.iter().map(|t| t.team).collect(),
+            mean_score: self.scores.iter().sum::<i64>() as f64 / runs,
+            low_score: percentile(10),
+            high_score: percentile(90),
+            win_probability: self.wins as f64 / runs,
+            expected_rp: self
+                .rp
+                .iter()
+                .map(|(&rp, &count)| rp as f64 * count as f64)
+                .sum::<f64>()
+                / runs,
+            rp_outcomes: self
+                .rp
+                .iter()
+                .map(|(&rp, &count)| (rp, count as f64 / runs))
+                .collect(),
+            bonus_probability: self
+                .bonuses
+                .iter()
+                .map(|(&name, &count)| (name, count as f64 / runs))
+                .collect(),
+        }
+    }
+}
+
+pub fn simulate(
+    red: &[TeamSamples],
+    blue: &[TeamSamples],
+    runs: usize,
+    game: &dyn Game,
+    rng: &mut impl Rng,
+) -> Prediction {
+    let mut red_tally = Tally::new(runs);
+    let mut blue_tally = Tally::new(runs);
+    let mut ties = 0;
+
+    for _ in 0..runs {
+        let (red_robots, red_score) = draw(red, rng);
+        let (blue_robots, blue_score) = draw(blue, rng);
+
+        red_tally.record(&red_robots, red_score, blue_score, game);
+        blue_tally.record(&blue_robots, blue_score, red_score, game);
+        ties += (red_score == blue_score) as usize;
+    }
+
+    Prediction {
+        red: red_tally.forecast(red),
+        blue: blue_tally.forecast(blue),
+        tie_probability: ties as f64 / runs.max(1) as f64,
+        runs,
+        unscouted: red
+            .iter()
+            .chain(blue)
+            .filter(|t| t.samples.is_empty())
+            .map(|t| t.team)
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{season, testing::match_response};
+
+    /// A robot that scored `upper` cargo in the upper hub in teleop, once per observed match.
+    fn team(team: u32, upper: &[i16]) -> TeamSamples {
+        let game = season::current();
+        let samples = upper
+            .iter()
+            .map(|&upper| {
+                let mut response = match_response(1, team);
+                response.teleop_scored_upper = upper;
+                Sample::new(&FormIngest::from(response), game)
+            })
+            .collect();
+
+        TeamSamples { team, samples }
+    }
+
+    #[test]
+    fn stronger_alliance_wins_most_runs() {
+        let red = [team(1, &[4, 8, 10]), team(2, &[6, 9]), team(3, &[5, 7])];
+        let blue = [team(4, &[0, 2, 12]), team(5, &[1, 3]), team(6, &[0])];
+        let mut rng = StdRng::seed_from_u64(42);
+
+        let prediction = simulate(&red, &blue, 2_000, season::current(), &mut rng);
+        assert_eq!(prediction.runs, 2_000);
+        assert!(prediction.unscouted.is_empty());
+        assert!(prediction.red.win_probability > 0.9);
+        assert!(prediction.blue.win_probability < 0.1);
+        assert!(prediction.red.mean_score > prediction.blue.mean_score);
+        assert!(prediction.red.low_score <= prediction.red.high_score);
+        assert!(prediction.red.expected_rp > 1.8);
+        assert_eq!(prediction.red.teams, [1, 2, 3]);
+
+        let total: f64 = prediction.red.rp_outcomes.values().sum();
+        assert!((total - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_prediction() {
+        let red = [team(1, &[0, 5, 10])];
+        let blue = [team(2, &[2, 4, 8])];
+        let run = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let p = simulate(&red, &blue, 500, season::current(), &mut rng);
+            (p.red.win_probability, p.tie_probability, p.red.mean_score)
+        };
+
+        assert_eq!(run(7), run(7));
+    }
+
+    #[test]
+    fn unscouted_alliances_tie_at_zero() {
+        let red = [team(1, &[]), team(2, &[])];
+        let blue = [team(3, &[])];
+        let mut rng = StdRng::seed_from_u64(1);
+
+        let prediction = simulate(&red, &blue, 100, season::current(), &mut rng);
+        assert_eq!(prediction.unscouted, [1, 2, 3]);
+        assert_eq!(prediction.tie_probability, 1.0);
+        assert_eq!(prediction.red.win_probability, 0.0);
+        assert_eq!(prediction.red.mean_score, 0.0);
+        assert_eq!(
+            (prediction.red.low_score, prediction.red.high_score),
+            (0, 0)
+        );
+        // a tie is still worth a ranking point
+        assert_eq!(prediction.red.expected_rp, 1.0);
+    }
+
+    #[test]
+    fn no_runs_predicts_nothing() {
+        let mut rng = StdRng::seed_from_u64(1);
+
+        let prediction = simulate(&[team(1, &[5])], &[], 0, season::current(), &mut rng);
+        assert_eq!(prediction.tie_probability, 0.0);
+        assert_eq!(prediction.red.mean_score, 0.0);
+        assert_eq!(prediction.red.expected_rp, 0.0);
+        assert!(prediction.blue.teams.is_empty());
+    }
+}
@@ -0,0 +1,350 @@
+//! Ranking points, both earned in played matches and projected over the rest of the schedule.
+//!
+//! Earned points come from the scouted final score and the robots' responses, so bonuses are
+//! only as good as the scouting: an alliance with a robot nobody watched may be short.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+use sqlx::{query_as, query_scalar, SqliteConnection};
+
+use crate::{
+    consensus,
+    db::MatchResponse,
+    game::{Alliance, FormIngest},
+    opr,
+    schedule::{self, ScheduledMatch},
+    season::{BonusRp, FieldValues, Game},
+};
+
+/// How one alliance did in one played match.
+#[derive(Debug, Serialize)]
+pub struct AllianceOutcome {
+    pub match_number: i16,
+    pub alliance: Alliance,
+    pub teams: Vec<u32>,
+    pub score: i32,
+    pub opponent_score: i32,
+    pub result_rp: u8,
+    pub bonuses: Vec<BonusRp>,
+    pub rp: u8,
+    /// Whether every robot on the alliance was scouted, so the bonuses can be trusted.
+    pub complete: bool,
+}
+
+/// Works out the ranking points of every alliance in an event's scouted matches.
+/// Responses without an alliance are placed using the schedule, or skipped if it has none.
+pub fn outcomes(
+    responses: Vec<MatchResponse>,
+    schedule: &[ScheduledMatch],
+    game: &dyn Game,
+) -> Vec<AllianceOutcome> {
+    let mut scheduled: HashMap<(i16, u32), Alliance> = HashMap::new();
+    let mut lineups: HashMap<(i16, Alliance), Vec<u32>> = HashMap::new();
+    for m in schedule {
+        for (alliance, _, team) in m.stations() {
+            scheduled.insert((m.match_number, team), alliance);
+            lineups
+                .entry((m.match_number, alliance))
+                .or_default()
+                .push(team);
+        }
+    }
+
+    let mut matches: BTreeMap<i16, Vec<MatchResponse>> = BTreeMap::new();
+    for response in consensus::canonical(responses) {
+        matches
+            .entry(response.match_number)
+            .or_default()
+            .push(response);
+    }
+
+    let mut outcomes = Vec::new();
+    for (match_number, robots) in matches {
+        let red: Vec<i32> = robots.iter().map(|r| r.red_score).collect();
+        let blue: Vec<i32> = robots.iter().map(|r| r.blue_score).collect();
+        let (red, blue) = (opr::mode(&red), opr::mode(&blue));
+
+        let mut sides: BTreeMap<Alliance, Vec<MatchResponse>> = BTreeMap::new();
+        for robot in robots {
+            let alliance = robot
+                .alliance
+                .or_else(|| scheduled.get(&(match_number, robot.team_number)).copied());
+            if let Some(alliance) = alliance {
+                sides.entry(alliance).or_default().push(robot);
+            }
+        }
+
+        for alliance in [Alliance::Red, Alliance::Blue] {
+            let robots = sides.remove(&alliance).unwrap_or_default();
+            let scouted: Vec<u32> = robots.iter().map(|r| r.team_number).collect();
+
+            // everyone on the alliance gets its ranking points, scouted or not
+            let (teams, complete) = match lineups.get(&(match_number, alliance)) {
+                Some(lineup) => (lineup.clone(), lineup.iter().all(|t| scouted.contains(t))),
+                None if scouted.is_empty() => continue,
+                None => (scouted.clone(), scouted.len() >= 3),
+            };
+
+            let (score, opponent_score) = match alliance {
+                Alliance::Red => (red, blue),
+                Alliance::Blue => (blue, red),
+            };
+
+            let observed: Vec<FormIngest> = robots.into_iter().map(FormIngest::from).collect();
+            let observed: Vec<&dyn FieldValues> =
+                observed.iter().map(|o| o as &dyn FieldValues).collect();
+
+            let result_rp = game.result_rp(score as i64, opponent_score as i64);
+            let bonuses = game.bonus_rp(&observed);
+
+            outcomes.push(AllianceOutcome {
+                match_number,
+                alliance,
+                complete,
+                teams,
+                score,
+                opponent_score,
+                result_rp,
+                rp: result_rp + bonuses.iter().filter(|b| b.earned).count() as u8,
+                bonuses,
+            });
+        }
+    }
+
+    outcomes
+}
+
+/// Reads and scores every played match at `event`.
+pub async fn event_outcomes(
+    conn: &mut SqliteConnection,
+    event: &str,
+    game: &dyn Game,
+) -> Result<Vec<AllianceOutcome>, sqlx::Error> {
+    let matches = schedule::load(conn, event).await?;
+    let responses = query_as::<_, MatchResponse>("SELECT * FROM match_responses WHERE event = ?")
+        .bind(event)
+        .fetch_all(&mut *conn)
+        .await?;
+
+    Ok(outcomes(responses, &matches, game))
+}
+
+/// Ranking points each team has earned across every event on file.
+pub async fn earned_rp(
+    conn: &mut SqliteConnection,
+    game: &dyn Game,
+) -> Result<HashMap<u32, i64>, sqlx::Error> {
+    let events: Vec<String> = query_scalar("SELECT DISTINCT event FROM match_responses")
+        .fetch_all(&mut *conn)
+        .await?;
+
+    let mut earned: HashMap<u32, i64> = HashMap::new();
+    for event in events {
+        for outcome in event_outcomes(conn, &event, game).await? {
+            for team in outcome.teams {
+                *earned.entry(team).or_default() += outcome.rp as i64;
+            }
+        }
+    }
+
+    Ok(earned)
+}
+
+/// A team's place in the qualification standings, now and at the end of the schedule.
+#[derive(Debug, Serialize)]
+pub struct Standing {
+    pub team: u32,
+    pub played: usize,
+    pub rp: i64,
+    /// Average ranking points per match, which is what FRC ranks on.
+    pub ranking_score: f64,
+    pub remaining: usize,
+    pub projected_rp: f64,
+    pub projected_ranking_score: f64,
+}
+
+/// An alliance in a match that hasn't been played, with the ranking points it should expect.
+#[derive(Debug, Clone)]
+pub struct Projection {
+    pub teams: Vec<u32>,
+    pub expected_rp: f64,
+}
+
+/// Combines earned and projected ranking points into standings, best projected first.
+pub fn standings(outcomes: &[AllianceOutcome], projections: &[Projection]) -> Vec<Standing> {
+    #[derive(Default)]
+    struct Totals {
+        played: usize,
+        rp: i64,
+        remaining: usize,
+        projected: f64,
+    }
+
+    let mut totals: BTreeMap<u32, Totals> = BTreeMap::new();
+    for outcome in outcomes {
+        for &team in &outcome.teams {
+            let t = totals.entry(team).or_default();
+            t.played += 1;
+            t.rp += outcome.rp as i64;
+        }
+    }
+    for projection in projections {
+        for &team in &projection.teams {
+            let t = totals.entry(team).or_default();
+            t.remaining += 1;
+            t.projected += projection.expected_rp;
+        }
+    }
+
+    let per = |total: f64, matches: usize| {
+        if matches == 0 {
+            0.0
+        } else {
+            total / matches as f64
+        }
+    };
+
+    let mut standings: Vec<Standing> = totals
+        .into_iter()
+        .map(|(team, t)| Standing {
+            team,
+            played: t.played,
+            rp: t.rp,
+            ranking_score: per(t.rp as f64, t.played),
+            remaining: t.remaining,
+            projected_rp: t.rp as f64 + t.projected,
+            projected_ranking_score: per(t.rp as f64 + t.projected, t.played + t.remaining),
+        })
+        .collect();
+
+    standings.sort_by(|a, b| {
+        b.projected_ranking_score
+            .partial_cmp(&a.projected_ranking_score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.team.cmp(&b.team))
+    });
+
+    standings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{season, testing::match_response};
+
+    /// A report of match `match_number` from a scout watching `team`.
+    fn report(match_number: i16, team: u32, red_score: i32, blue_score: i32) -> MatchResponse {
+        let mut response = match_response(match_number, team);
+        response.red_score = red_score;
+        response.blue_score = blue_score;
+        response
+    }
+
+    fn scheduled(match_number: i16) -> ScheduledMatch {
+        ScheduledMatch {
+            match_number,
+            red: vec![1, 2, 3],
+            blue: vec![4, 5, 6],
+        }
+    }
+
+    #[test]
+    fn wins_ties_and_losses_earn_result_rp() {
+        let schedule = [scheduled(1), scheduled(2), scheduled(3)];
+        let responses = vec![
+            report(1, 1, 60, 40),
+            report(1, 4, 60, 40),
+            report(2, 1, 50, 50),
+            report(3, 4, 30, 70),
+        ];
+
+        let outcomes = outcomes(responses, &schedule, season::current());
+        let rp: Vec<(i16, Alliance, u8)> = outcomes
+            .iter()
+            .map(|o| (o.match_number, o.alliance, o.result_rp))
+            .collect();
+        assert_eq!(
+            rp,
+            [
+                (1, Alliance::Red, 2),
+                (1, Alliance::Blue, 0),
+                (2, Alliance::Red, 1),
+                (2, Alliance::Blue, 1),
+                (3, Alliance::Red, 0),
+                (3, Alliance::Blue, 2),
+            ]
+        );
+
+        // robots nobody watched still share their alliance's points
+        assert_eq!(outcomes[0].teams, [1, 2, 3]);
+        assert!(!outcomes[0].complete);
+        assert!(outcomes.iter().all(|o| o.rp == o.result_rp));
+    }
+
+    #[test]
+    fn bonuses_count_towards_rp() {
+        let responses = [1, 2, 3]
+            .iter()
+            .map(|&team| {
+                let mut response = report(1, team, 40, 20);
+                response.climb = 2;
+                response
+            })
+            .collect();
+
+        let outcomes = outcomes(responses, &[scheduled(1)], season::current());
+        let red = &outcomes[0];
+        assert!(red.complete);
+        assert_eq!((red.result_rp, red.rp), (2, 3));
+        assert!(red.bonuses.iter().any(|b| b.name == "hangar" && b.earned));
+    }
+
+    #[test]
+    fn unplaced_responses_are_skipped() {
+        let mut placed = report(1, 254, 10, 20);
+        placed.alliance = Some(Alliance::Blue);
+
+        let outcomes = outcomes(
+            vec![placed, report(1, 1678, 10, 20)],
+            &[],
+            season::current(),
+        );
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[0].teams, [254]);
+        assert_eq!(outcomes[0].alliance, Alliance::Blue);
+        assert_eq!(outcomes[0].result_rp, 2);
+    }
+
+    #[test]
+    fn standings_rank_by_projected_ranking_score() {
+        let responses = vec![report(1, 1, 60, 40), report(2, 4, 30, 70)];
+        let outcomes = outcomes(responses, &[scheduled(1), scheduled(2)], season::current());
+        let projections = [
+            Projection {
+                teams: vec![1, 2, 3],
+                expected_rp: 0.5,
+            },
+            Projection {
+                teams: vec![7],
+                expected_rp: 3.0,
+            },
+        ];
+
+        let standings = standings(&outcomes, &projections);
+        let order: Vec<u32> = standings.iter().map(|s| s.team).collect();
+        // team 7 has only a projected match, and everyone else is tied on 2 RP from 2 played
+        assert_eq!(order, [7, 4, 5, 6, 1, 2, 3]);
+
+        let first = &standings[4];
+        assert_eq!((first.played, first.rp, first.remaining), (2, 2, 1));
+        assert_eq!(first.ranking_score, 1.0);
+        assert_eq!(first.projected_rp, 2.5);
+        assert!((first.projected_ranking_score - 2.5 / 3.0).abs() < 1e-9);
+
+        let unplayed = &standings[0];
+        assert_eq!((unplayed.played, unplayed.rp), (0, 0));
+        assert_eq!(unplayed.ranking_score, 0.0);
+        assert_eq!(unplayed.projected_ranking_score, 3.0);
+    }
+}
@@ -0,0 +1,391 @@
+//! Scout reliability, judged by how well scouted robots add up to the real alliance score.
+//!
+//! For every alliance in a match where every robot was scouted, the estimated contributions
+//! are summed and compared with the alliance score the scouts reported. The difference is
+//! split evenly over the robots' scouts. Fouls and anything else the form doesn't capture
+//! push every scout's error the same way, so ratings are best read against each other.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+
+use crate::{
+    db::MatchResponse,
+    game::{Alliance, FormIngest},
+    opr,
+    schedule::ScheduledMatch,
+    season::Game,
+};
+
+/// Alliances missing by more than this many points, and by more than [`FLAG_RATIO`] of
+/// their score, are flagged.
+pub const FLAG_POINTS: f64 = 10.0;
+pub const FLAG_RATIO: f64 = 0.25;
+
+/// A match response reduced to what the cross-check needs.
+#[derive(Debug, Clone)]
+pub struct Observation {
+    pub id: i64,
+    pub uuid: u32,
+    pub match_number: i16,
+    pub team_number: u32,
+    pub alliance: Option<Alliance>,
+    pub red_score: i32,
+    pub blue_score: i32,
+    /// The robot's estimated contribution under the season's point values.
+    pub contribution: i64,
+}
+
+impl Observation {
+    pub fn new(response: MatchResponse, game: &dyn Game) -> Self {
+        let (id, uuid, alliance) = (response.id, response.uuid, response.alliance);
+        let (match_number, team_number) = (response.match_number, response.team_number);
+        let (red_score, blue_score) = (response.red_score, response.blue_score);
+
+        Observation {
+            id,
+            uuid,
+            match_number,
+            team_number,
+            alliance,
+            red_score,
+            blue_score,
+            contribution: game.score(&FormIngest::from(response)),
+        }
+    }
+}
+
+/// One match's error, as attributed to a scout.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ErrorSample {
+    pub match_number: i16,
+    /// Share of the alliance's error, in points. Positive means the scouted robots were
+    /// credited with more than the alliance actually scored.
+    pub error: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScoutRating {
+    pub uuid: u32,
+    /// Responses from this scout that made it into a cross-checked alliance.
+    pub checked: usize,
+    pub mean_error: f64,
+    pub mean_abs_error: f64,
+    /// 1 for a perfect record, falling towards 0 as the typical error approaches the typical
+    /// robot's share of the score. `None` until the scout has been cross-checked at all.
+    pub accuracy: Option<f64>,
+    /// Times the scout reported a final score the other scouts disagreed with.
+    pub score_mismatches: usize,
+    /// Oldest match first.
+    pub history: Vec<ErrorSample>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum FlagReason {
+    /// The scouted robots add up to far more or less than the alliance scored.
+    AllianceTotal { scouted: i64, reported: i32 },
+    /// The response's final score disagrees with what most scouts reported.
+    ScoreMismatch {
+        red_score: i32,
+        blue_score: i32,
+        consensus_red: i32,
+        consensus_blue: i32,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub struct Flag {
+    pub id: i64,
+    pub uuid: u32,
+    pub match_number: i16,
+    pub team_number: u32,
+    #[serde(flatten)]
+    pub reason: FlagReason,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Reliability {
+    pub scouts: Vec<ScoutRating>,
+    pub flags: Vec<Flag>,
+}
+
+/// Fills in alliances missing from older responses using the schedule.
+fn alliance_of(obs: &Observation, schedule: &HashMap<(i16, u32), Alliance>) -> Option<Alliance> {
+    obs.alliance
+        .or_else(|| schedule.get(&(obs.match_number, obs.team_number)).copied())
+}
+
+/// Rates every scout with a response in `observations`, which should all be from the event
+/// `schedule` belongs to. The schedule may be empty; alliances are then assumed to be full.
+pub fn evaluate(observations: &[Observation], schedule: &[ScheduledMatch]) -> Reliability {
+    let mut sizes: HashMap<(i16, Alliance), usize> = HashMap::new();
+    let mut scheduled: HashMap<(i16, u32), Alliance> = HashMap::new();
+    for m in schedule {
+        for (alliance, _, team) in m.stations() {
+            *sizes.entry((m.match_number, alliance)).or_default() += 1;
+            scheduled.insert((m.match_number, team), alliance);
+        }
+    }
+
+    let mut matches: BTreeMap<i16, Vec<&Observation>> = BTreeMap::new();
+    for obs in observations {
+        matches.entry(obs.match_number).or_default().push(obs);
+    }
+
+    let mut samples: BTreeMap<u32, Vec<ErrorSample>> = BTreeMap::new();
+    let mut mismatches: HashMap<u32, usize> = HashMap::new();
+    let mut flags = Vec::new();
+    let mut shares = Vec::new();
+
+    for (&match_number, observed) in &matches {
+        let red: Vec<i32> = observed.iter().map(|o| o.red_score).collect();
+        let blue: Vec<i32> = observed.iter().map(|o| o.blue_score).collect();
+        let (red, blue) = (opr::mode(&red), opr::mode(&blue));
+
+        for obs in observed {
+            if obs.red_score != red || obs.blue_score != blue {
+                *mismatches.entry(obs.uuid).or_default() += 1;
+                flags.push(Flag {
+                    id: obs.id,
+                    uuid: obs.uuid,
+                    match_number,
+                    team_number: obs.team_number,
+                    reason: FlagReason::ScoreMismatch {
+                        red_score: obs.red_score,
+                        blue_score: obs.blue_score,
+                        consensus_red: red,
+                        consensus_blue: blue,
+                    },
+                });
+            }
+        }
+
+        for (alliance, reported) in [(Alliance::Red, red), (Alliance::Blue, blue)] {
+            // one response per robot; double-scouted robots use the first
+            let mut robots: BTreeMap<u32, &Observation> = BTreeMap::new();
+            for obs in observed {
+                if alliance_of(obs, &scheduled) == Some(alliance) {
+                    robots.entry(obs.team_number).or_insert(obs);
+                }
+            }
+
+            let size = sizes.get(&(match_number, alliance)).copied().unwrap_or(3);
+            if robots.is_empty() || robots.len() != size {
+                continue;
+            }
+
+            let scouted: i64 = robots.values().map(|o| o.contribution).sum();
+            let error = (scouted - reported as i64) as f64;
+            let share = error / size as f64;
+            shares.push(reported as f64 / size as f64);
+
+            let flagged =
+                error.abs() > FLAG_POINTS && error.abs() > FLAG_RATIO * (reported as f64).abs();
+
+            for obs in robots.values() {
+                samples.entry(obs.uuid).or_default().push(ErrorSample {
+                    match_number,
+                    error: share,
+                });
+
+                if flagged {
+                    flags.push(Flag {
+                        id: obs.id,
+                        uuid: obs.uuid,
+                        match_number,
+                        team_number: obs.team_number,
+                        reason: FlagReason::AllianceTotal { scouted, reported },
+                    });
+                }
+            }
+        }
+    }
+
+    let typical_share = mean(shares.iter().copied()).max(1.0);
+
+    let mut uuids: Vec<u32> = observations.iter().map(|o| o.uuid).collect();
+    uuids.sort_unstable();
+    uuids.dedup();
+
+    let scouts = uuids
+        .into_iter()
+        .map(|uuid| {
+            let history = samples.remove(&uuid).unwrap_or_default();
+            let mean_abs_error = mean(history.iter().map(|s| s.error.abs()));
+
+            ScoutRating {
+                uuid,
+                checked: history.len(),
+                mean_error: mean(history.iter().map(|s| s.error)),
+                mean_abs_error,
+                accuracy: (!history.is_empty())
+                    .then(|| (1.0 - mean_abs_error / typical_share).clamp(0.0, 1.0)),
+                score_mismatches: mismatches.get(&uuid).copied().unwrap_or(0),
+                history,
+            }
+        })
+        .collect();
+
+    Reliability { scouts, flags }
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> f64 {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+    if count == 0 {
+        0.0
+    } else {
+        sum / count as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observation(
+        id: i64,
+        uuid: u32,
+        team_number: u32,
+        alliance: Option<Alliance>,
+        contribution: i64,
+    ) -> Observation {
+        Observation {
+            id,
+            uuid,
+            match_number: 1,
+            team_number,
+            alliance,
+            red_score: 30,
+            blue_score: 30,
+            contribution,
+        }
+    }
+
+    /// Scout 1 watches the red robots, scout 2 the blue ones, with the given contributions.
+    fn match_one(red: [i64; 3], blue: [i64; 3]) -> Vec<Observation> {
+        let red = (1..=3)
+            .zip(red)
+            .map(|(team, c)| (1, team, Alliance::Red, c));
+        let blue = (4..=6)
+            .zip(blue)
+            .map(|(team, c)| (2, team, Alliance::Blue, c));
+        red.chain(blue)
+            .enumerate()
+            .map(|(i, (uuid, team, alliance, c))| {
+                observation(i as i64 + 1, uuid, team, Some(alliance), c)
+            })
+            .collect()
+    }
+
+    fn rating(reliability: &Reliability, uuid: u32) -> &ScoutRating {
+        reliability.scouts.iter().find(|s| s.uuid == uuid).unwrap()
+    }
+
+    #[test]
+    fn scouts_are_rated_by_how_well_their_alliance_adds_up() {
+        let reliability = evaluate(&match_one([10, 10, 10], [20, 20, 20]), &[]);
+
+        let exact = rating(&reliability, 1);
+        assert_eq!(exact.checked, 3);
+        assert_eq!((exact.mean_error, exact.mean_abs_error), (0.0, 0.0));
+        assert_eq!(exact.accuracy, Some(1.0));
+
+        // blue was credited with 60 of its 30 points, 10 too many per robot
+        let over = rating(&reliability, 2);
+        assert_eq!(over.checked, 3);
+        assert_eq!(over.mean_error, 10.0);
+        assert_eq!(over.accuracy, Some(0.0));
+
+        assert_eq!(reliability.flags.len(), 3);
+        for flag in &reliability.flags {
+            assert_eq!(flag.uuid, 2);
+            assert_eq!(
+                flag.reason,
+                FlagReason::AllianceTotal {
+                    scouted: 60,
+                    reported: 30
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn small_misses_are_not_flagged() {
+        let reliability = evaluate(&match_one([10, 10, 10], [12, 12, 12]), &[]);
+
+        assert_eq!(rating(&reliability, 2).mean_error, 2.0);
+        assert!(reliability.flags.is_empty());
+    }
+
+    #[test]
+    fn partly_scouted_alliances_are_not_checked() {
+        let mut observations = match_one([10, 10, 10], [20, 20, 20]);
+        observations.truncate(5);
+
+        let reliability = evaluate(&observations, &[]);
+        let blue = rating(&reliability, 2);
+        assert_eq!(blue.checked, 0);
+        assert_eq!(blue.accuracy, None);
+        assert!(blue.history.is_empty());
+        assert_eq!(rating(&reliability, 1).checked, 3);
+    }
+
+    #[test]
+    fn schedule_fills_in_alliances_and_sizes() {
+        let schedule = [ScheduledMatch {
+            match_number: 1,
+            red: vec![1, 2],
+            blue: vec![4, 5, 6],
+        }];
+        let observations = vec![
+            observation(1, 1, 1, None, 15),
+            observation(2, 1, 2, None, 15),
+            observation(3, 2, 4, None, 10),
+        ];
+
+        let reliability = evaluate(&observations, &schedule);
+        let red = rating(&reliability, 1);
+        assert_eq!(red.checked, 2);
+        assert_eq!(red.mean_error, 0.0);
+        assert_eq!(rating(&reliability, 2).checked, 0);
+    }
+
+    #[test]
+    fn double_scouted_robots_count_once() {
+        let mut observations = match_one([10, 10, 10], [10, 10, 10]);
+        observations.push(observation(7, 3, 1, Some(Alliance::Red), 40));
+
+        let reliability = evaluate(&observations, &[]);
+        assert_eq!(rating(&reliability, 1).mean_error, 0.0);
+        assert_eq!(rating(&reliability, 3).checked, 0);
+    }
+
+    #[test]
+    fn disagreeing_final_scores_are_flagged() {
+        let mut observations = match_one([10, 10, 10], [10, 10, 10]);
+        observations[5].red_score = 31;
+
+        let reliability = evaluate(&observations, &[]);
+        assert_eq!(rating(&reliability, 2).score_mismatches, 1);
+        assert_eq!(rating(&reliability, 1).score_mismatches, 0);
+        assert_eq!(reliability.flags.len(), 1);
+        assert_eq!(reliability.flags[0].id, 6);
+        assert_eq!(
+            reliability.flags[0].reason,
+            FlagReason::ScoreMismatch {
+                red_score: 31,
+                blue_score: 30,
+                consensus_red: 30,
+                consensus_blue: 30,
+            }
+        );
+    }
+
+    #[test]
+    fn no_observations_rate_nobody() {
+        let reliability = evaluate(&[], &[]);
+        assert!(reliability.scouts.is_empty());
+        assert!(reliability.flags.is_empty());
+    }
+}
@@ -0,0 +1,106 @@
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use specialscout_db::{
+    aggregate::{self, Drift},
+    audit::AuditEntry,
+};
+use sqlx::query_as;
+
+use crate::{error::ApiError, routes::auth::require_admin, AppState};
+
+#[derive(Deserialize)]
+pub struct EventScope {
+    event: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RebuildReport {
+    teams: usize,
+}
+
+#[derive(Serialize)]
+struct ConsistencyReport {
+    consistent: bool,
+    drift: Vec<Drift>,
+}
+
+/// Throws away the stored `team_details` for every team in scope and rebuilds them from
+/// the raw responses.
+#[actix_web::post("/admin/rebuild")]
+pub async fn rebuild(
+    scope: web::Query<EventScope>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require_admin(&req, &data)?;
+
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
+
+    let teams = aggregate::rebuild(&mut tx, scope.event.as_deref(), data.game)
+        .await
+        .map_err(|e| ApiError::storage("Failed rebuilding team details", e))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| ApiError::storage("Failed committing rebuilt team details", e))?;
+
+    Ok(HttpResponse::Ok().json(RebuildReport { teams }))
+}
+
+/// Reports where the stored `team_details` differ from what the responses add up to.
+#[actix_web::get("/admin/consistency")]
+pub async fn consistency(
+    scope: web::Query<EventScope>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require_admin(&req, &data)?;
+
+    let mut conn = data
+        .db
+        .acquire()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
+
+    let drift = aggregate::check(&mut conn, scope.event.as_deref(), data.game)
+        .await
+        .map_err(|e| ApiError::storage("Failed checking team details", e))?;
+
+    Ok(HttpResponse::Ok().json(ConsistencyReport {
+        consistent: drift.is_empty(),
+        drift,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct AuditFilter {
+    table: Option<String>,
+    row_id: Option<i64>,
+}
+
+/// Every correction and retraction, newest first.
+#[actix_web::get("/admin/audit")]
+pub async fn audit_log(
+    filter: web::Query<AuditFilter>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require_admin(&req, &data)?;
+
+    let entries = query_as::<_, AuditEntry>(
+        r#"SELECT * FROM audit_log
+            WHERE (?1 IS NULL OR table_name = ?1) AND (?2 IS NULL OR row_id = ?2)
+            ORDER BY id DESC"#,
+    )
+    .bind(&filter.table)
+    .bind(filter.row_id)
+    .fetch_all(&data.db)
+    .await
+    .map_err(|e| ApiError::storage("Failed reading audit log", e))?;
+
+    Ok(HttpResponse::Ok().json(entries))
+}
@@ -0,0 +1,118 @@
+use std::fmt;
+
+use actix_web::{http::header::AUTHORIZATION, Error, HttpRequest};
+use specialscout_db::scouts::hash_token;
+use sqlx::query_scalar;
+
+use crate::{error::ApiError, AppState};
+
+/// The token from an `Authorization: Bearer <token>` header.
+pub fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+/// Checks the request carries the configured admin token. Without one configured, admin
+/// routes are refused unless the operator has opted out with `open_admin`.
+pub fn require_admin(req: &HttpRequest, data: &AppState) -> Result<(), Error> {
+    let expected = match &data.config.admin_token {
+        Some(expected) => expected,
+        None if data.config.open_admin => return Ok(()),
+        None => {
+            return Err(ApiError::Forbidden(
+                "Admin routes are disabled until an admin_token is configured".to_string(),
+            )
+            .into())
+        }
+    };
+
+    let token = bearer_token(req)
+        .ok_or_else(|| ApiError::Unauthorized("Missing admin token".to_string()))?;
+
+    // compare digests so the time taken doesn't leak how much of the token matched
+    if hash_token(token) == hash_token(expected) {
+        Ok(())
+    } else {
+        Err(ApiError::Forbidden("Wrong admin token".to_string()).into())
+    }
+}
+
+/// Who an authenticated request comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Caller {
+    Admin,
+    Scout(u32),
+}
+
+/// How a caller is recorded in the audit log.
+impl fmt::Display for Caller {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Caller::Admin => write!(f, "admin"),
+            Caller::Scout(uuid) => write!(f, "scout {}", uuid),
+        }
+    }
+}
+
+impl Caller {
+    /// Checks the caller may change a response filed by scout `owner`. The admin can change
+    /// any response, a scout only their own.
+    pub fn may_change(self, owner: u32) -> Result<(), ApiError> {
+        match self {
+            Caller::Admin => Ok(()),
+            Caller::Scout(uuid) if uuid == owner => Ok(()),
+            Caller::Scout(_) => Err(ApiError::Forbidden(
+                "Scouts can only change their own responses".to_string(),
+            )),
+        }
+    }
+}
+
+/// Works out who sent a request from its bearer token, which may be the admin token or any
+/// scout's.
+pub async fn identify(req: &HttpRequest, data: &AppState) -> Result<Caller, Error> {
+    let token =
+        bearer_token(req).ok_or_else(|| ApiError::Unauthorized("Missing token".to_string()))?;
+
+    if let Some(expected) = &data.config.admin_token {
+        if hash_token(token) == hash_token(expected) {
+            return Ok(Caller::Admin);
+        }
+    }
+
+    let scout: Option<u32> = query_scalar("SELECT uuid FROM uuids WHERE token_hash = ?")
+        .bind(hash_token(token))
+        .fetch_optional(&data.db)
+        .await
+        .map_err(|e| ApiError::storage("Failed checking token", e))?;
+
+    match scout {
+        Some(uuid) => Ok(Caller::Scout(uuid)),
+        None => Err(ApiError::Unauthorized("Unknown token".to_string()).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::ResponseError;
+
+    use super::*;
+
+    #[test]
+    fn admin_may_change_any_response() {
+        assert!(Caller::Admin.may_change(1).is_ok());
+        assert!(Caller::Admin.may_change(7).is_ok());
+    }
+
+    #[test]
+    fn scouts_may_only_change_their_own_responses() {
+        assert!(Caller::Scout(7).may_change(7).is_ok());
+
+        let refused = Caller::Scout(7).may_change(8).unwrap_err();
+        assert_eq!(refused.code(), "forbidden");
+        assert_eq!(refused.status_code().as_u16(), 403);
+    }
+}
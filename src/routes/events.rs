@@ -0,0 +1,578 @@
+use std::collections::{hash_map, BTreeMap, HashMap, HashSet};
+
+use actix_web::{
+    http::header::{CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+    web, Error, HttpRequest, HttpResponse,
+};
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use specialscout_db::{
+    consensus,
+    coverage::{self, ScoutedSlot},
+    db::{MatchResponse, PitResponse, TeamDetails, PIT_RESPONSE_COLUMNS},
+    discrepancy,
+    game::FormIngest,
+    opr::{self, ScoreReport},
+    picklist::{self, Entry, PickList},
+    predict::{self, TeamSamples},
+    ranking::{self, Projection},
+    reliability::{self, Observation},
+    schedule::{self, Assignment, ScheduledMatch},
+};
+use sqlx::{query, query_as, query_scalar};
+
+use crate::{
+    error::ApiError,
+    routes::auth::{identify, require_admin},
+    AppState,
+};
+
+#[derive(Serialize)]
+struct ActiveEvent<'a> {
+    event: Option<&'a str>,
+}
+
+/// The event the server is configured for, so tablets know what to tag responses with.
+#[actix_web::get("/events/active")]
+pub async fn get_active_event(data: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(ActiveEvent {
+        event: data.config.event.as_deref(),
+    })
+}
+
+/// OPR, DPR and CCWM for every team at an event, from the scores scouts recorded.
+#[actix_web::get("/events/{event}/opr")]
+pub async fn get_opr(
+    web::Path((event,)): web::Path<(String,)>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let mut conn = data
+        .db
+        .acquire()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
+
+    let reports = query_as::<_, ScoreReport>(
+        r#"SELECT match_number, team_number, alliance, red_score, blue_score
+            FROM match_responses WHERE event = ?"#,
+    )
+    .bind(&event)
+    .fetch_all(&mut conn)
+    .await
+    .map_err(|e| ApiError::storage("Failed reading match responses", e))?;
+    let matches = schedule::load(&mut conn, &event)
+        .await
+        .map_err(|e| ApiError::storage("Failed reading schedule", e))?;
+
+    let results = opr::alliance_results(&reports, &matches);
+    let mut ratings = opr::solve(&results);
+    ratings.sort_by(|a, b| {
+        b.opr
+            .partial_cmp(&a.opr)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    Ok(HttpResponse::Ok().json(ratings))
+}
+
+/// Imports an event's qualification schedule, replacing any stored one. Admin only.
+///
+/// Takes either a JSON list of matches or, with `Content-Type: text/csv`, a CSV with a
+/// `match_number,red1,red2,red3,blue1,blue2,blue3` header.
+#[actix_web::put("/events/{event}/schedule")]
+pub async fn put_schedule(
+    web::Path((event,)): web::Path<(String,)>,
+    req: HttpRequest,
+    body: web::Bytes,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require_admin(&req, &data)?;
+
+    let content_type = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    let is_csv = matches!(content_type, Some(v) if v.starts_with("text/csv"));
+
+    let parsed = if is_csv {
+        schedule::parse_csv(&body)
+    } else {
+        serde_json::from_slice::<Vec<ScheduledMatch>>(&body).map_err(|e| e.to_string())
+    };
+
+    let matches = match parsed.and_then(|m| schedule::validate(&m).map(|_| m)) {
+        Ok(matches) => matches,
+        Err(reason) => return Err(ApiError::Invalid(reason).into()),
+    };
+
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
+
+    schedule::store(&mut tx, &event, &matches)
+        .await
+        .map_err(|e| ApiError::storage("Failed storing schedule", e))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| ApiError::storage("Failed committing schedule", e))?;
+
+    Ok(HttpResponse::Ok().json(matches))
+}
+
+#[actix_web::get("/events/{event}/schedule")]
+pub async fn get_schedule(
+    web::Path((event,)): web::Path<(String,)>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let mut conn = data
+        .db
+        .acquire()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
+
+    let matches = schedule::load(&mut conn, &event)
+        .await
+        .map_err(|e| ApiError::storage("Failed reading schedule", e))?;
+
+    Ok(HttpResponse::Ok().json(matches))
+}
+
+#[derive(Deserialize)]
+pub struct Roster {
+    /// Scouts to hand out, in rotation order. Every registered scout otherwise.
+    scouts: Option<Vec<u32>>,
+}
+
+/// Generates scouting assignments for an event's schedule, replacing any existing ones.
+/// Admin only.
+#[actix_web::post("/events/{event}/assignments")]
+pub async fn generate_assignments(
+    web::Path((event,)): web::Path<(String,)>,
+    roster: Option<web::Json<Roster>>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require_admin(&req, &data)?;
+
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
+
+    let scouts = match roster.and_then(|r| r.into_inner().scouts) {
+        Some(scouts) => scouts,
+        None => query_scalar("SELECT uuid FROM uuids ORDER BY uuid")
+            .fetch_all(&mut tx)
+            .await
+            .map_err(|e| ApiError::storage("Failed reading scouts", e))?,
+    };
+
+    let matches = schedule::load(&mut tx, &event)
+        .await
+        .map_err(|e| ApiError::storage("Failed reading schedule", e))?;
+
+    if matches.is_empty() {
+        return Err(ApiError::NotFound(format!("No schedule for event {}", event)).into());
+    }
+
+    let assignments = schedule::assign(&matches, &scouts);
+
+    query("DELETE FROM assignments WHERE event = ?")
+        .bind(&event)
+        .execute(&mut tx)
+        .await
+        .map_err(|e| ApiError::storage("Failed clearing assignments", e))?;
+
+    for a in &assignments {
+        query("INSERT INTO assignments VALUES (?, ?, ?, ?, ?, ?)")
+            .bind(&event)
+            .bind(a.match_number)
+            .bind(a.uuid)
+            .bind(a.alliance)
+            .bind(a.station)
+            .bind(a.team_number)
+            .execute(&mut tx)
+            .await
+            .map_err(|e| ApiError::storage("Failed storing assignments", e))?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| ApiError::storage("Failed committing assignments", e))?;
+
+    Ok(HttpResponse::Ok().json(assignments))
+}
+
+#[actix_web::get("/events/{event}/assignments")]
+pub async fn get_assignments(
+    web::Path((event,)): web::Path<(String,)>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let assignments = query_as::<_, Assignment>(
+        r#"SELECT match_number, uuid, alliance, station, team_number FROM assignments
+            WHERE event = ? ORDER BY match_number, uuid"#,
+    )
+    .bind(&event)
+    .fetch_all(&data.db)
+    .await
+    .map_err(|e| ApiError::storage("Failed reading assignments", e))?;
+
+    Ok(HttpResponse::Ok().json(assignments))
+}
+
+/// Which scheduled robots have no response, more than one, or responses that don't match
+/// the schedule at all.
+#[actix_web::get("/events/{event}/coverage")]
+pub async fn get_coverage(
+    web::Path((event,)): web::Path<(String,)>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let mut conn = data
+        .db
+        .acquire()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
+
+    let matches = schedule::load(&mut conn, &event)
+        .await
+        .map_err(|e| ApiError::storage("Failed reading schedule", e))?;
+
+    if matches.is_empty() {
+        return Err(ApiError::NotFound(format!("No schedule for event {}", event)).into());
+    }
+
+    let assignments = query_as::<_, Assignment>(
+        "SELECT match_number, uuid, alliance, station, team_number FROM assignments WHERE event = ?",
+    )
+    .bind(&event)
+    .fetch_all(&mut conn)
+    .await
+    .map_err(|e| ApiError::storage("Failed reading assignments", e))?;
+
+    let responses = query_as::<_, ScoutedSlot>(
+        r#"SELECT id, uuid, match_number, team_number FROM match_responses
+            WHERE event = ? ORDER BY id"#,
+    )
+    .bind(&event)
+    .fetch_all(&mut conn)
+    .await
+    .map_err(|e| ApiError::storage("Failed reading match responses", e))?;
+
+    Ok(HttpResponse::Ok().json(coverage::report(&matches, &assignments, &responses)))
+}
+
+/// Rates each scout by how well their robots add up to the reported alliance scores, and
+/// flags the responses that look wrong.
+#[actix_web::get("/events/{event}/reliability")]
+pub async fn get_reliability(
+    web::Path((event,)): web::Path<(String,)>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let mut conn = data
+        .db
+        .acquire()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
+
+    let matches = schedule::load(&mut conn, &event)
+        .await
+        .map_err(|e| ApiError::storage("Failed reading schedule", e))?;
+
+    let observations: Vec<Observation> = query_as::<_, MatchResponse>(
+        "SELECT * FROM match_responses WHERE event = ? ORDER BY match_number, id",
+    )
+    .bind(&event)
+    .fetch_all(&mut conn)
+    .await
+    .map_err(|e| ApiError::storage("Failed reading match responses", e))?
+    .into_iter()
+    .map(|r| Observation::new(r, data.game))
+    .collect();
+
+    Ok(HttpResponse::Ok().json(reliability::evaluate(&observations, &matches)))
+}
+
+/// A pick list's version as an `ETag`.
+fn picklist_etag(version: i64) -> String {
+    format!("\"{}\"", version)
+}
+
+/// The saved pick list for an event, or a fresh one seeded from `team_details` for every
+/// team that has played or is scheduled there.
+///
+/// The version is sent as an `ETag`, so laptops polling with `If-None-Match` get a
+/// `304 Not Modified` until somebody saves.
+#[actix_web::get("/events/{event}/picklist")]
+pub async fn get_picklist(
+    web::Path((event,)): web::Path<(String,)>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let mut conn = data
+        .db
+        .acquire()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
+
+    let stored = picklist::load(&mut conn, &event)
+        .await
+        .map_err(|e| ApiError::storage("Failed reading pick list", e))?;
+
+    let list = match stored {
+        Some(list) => list,
+        None => {
+            let details = query_as::<_, TeamDetails>(
+                r#"SELECT * FROM team_details WHERE team IN (
+                    SELECT team_number FROM match_responses WHERE event = ?1
+                    UNION SELECT team_number FROM match_teams WHERE event = ?1
+                )"#,
+            )
+            .bind(&event)
+            .fetch_all(&mut conn)
+            .await
+            .map_err(|e| ApiError::storage("Failed reading team details", e))?;
+
+            PickList {
+                event,
+                version: 0,
+                updated_at: None,
+                updated_by: None,
+                teams: picklist::seed(details),
+            }
+        }
+    };
+
+    let etag = picklist_etag(list.version);
+    let unchanged = req
+        .headers()
+        .get(IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        == Some(etag.as_str());
+
+    if unchanged {
+        return Ok(HttpResponse::NotModified().header(ETAG, etag).finish());
+    }
+
+    Ok(HttpResponse::Ok().header(ETAG, etag).json(list))
+}
+
+#[derive(Deserialize)]
+pub struct PickListUpdate {
+    /// The version this edit was made against; 0 to save a seeded list for the first time.
+    version: i64,
+    teams: Vec<Entry>,
+}
+
+/// Saves a reordered, struck or annotated pick list as whoever the bearer token belongs to.
+/// If somebody else saved since `version`, nothing is changed and a `409 Conflict` carries
+/// the current version as its `ETag`.
+#[actix_web::put("/events/{event}/picklist")]
+pub async fn put_picklist(
+    web::Path((event,)): web::Path<(String,)>,
+    req: HttpRequest,
+    update: web::Json<PickListUpdate>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let caller = identify(&req, &data).await?;
+
+    if let Err(reason) = picklist::validate(&update.teams) {
+        return Err(ApiError::Invalid(reason).into());
+    }
+
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
+
+    let saved = picklist::store(
+        &mut tx,
+        &event,
+        update.version,
+        &caller.to_string(),
+        &update.teams,
+    )
+    .await
+    .map_err(|e| ApiError::storage("Failed storing pick list", e))?;
+
+    let version = match saved {
+        Some(version) => version,
+        None => {
+            let current = picklist::load(&mut tx, &event)
+                .await
+                .map_err(|e| ApiError::storage("Failed reading pick list", e))?
+                .map_or(0, |list| list.version);
+            return Err(ApiError::Conflict {
+                message: format!(
+                    "Pick list is at version {}, not {}; fetch it and try again",
+                    current, update.version
+                ),
+                etag: picklist_etag(current),
+            }
+            .into());
+        }
+    };
+
+    let list = picklist::load(&mut tx, &event)
+        .await
+        .map_err(|e| ApiError::storage("Failed reading pick list", e))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| ApiError::storage("Failed committing pick list", e))?;
+
+    Ok(HttpResponse::Ok()
+        .header(ETAG, picklist_etag(version))
+        .json(list))
+}
+
+/// Ranking points earned by every alliance in the event's scouted matches, with the bonus
+/// breakdown.
+#[actix_web::get("/events/{event}/rp")]
+pub async fn get_ranking_points(
+    web::Path((event,)): web::Path<(String,)>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let mut conn = data
+        .db
+        .acquire()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
+
+    let outcomes = ranking::event_outcomes(&mut conn, &event, data.game)
+        .await
+        .map_err(|e| ApiError::storage("Failed reading match responses", e))?;
+
+    Ok(HttpResponse::Ok().json(outcomes))
+}
+
+#[derive(Deserialize)]
+pub struct Forecast {
+    /// Simulations per remaining match.
+    runs: Option<usize>,
+    seed: Option<u64>,
+}
+
+/// Current qualification standings, plus where each team should finish once the scheduled
+/// matches nobody has scouted yet are simulated.
+#[actix_web::get("/events/{event}/rankings")]
+pub async fn get_rankings(
+    web::Path((event,)): web::Path<(String,)>,
+    forecast: web::Query<Forecast>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let mut conn = data
+        .db
+        .acquire()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
+
+    let outcomes = ranking::event_outcomes(&mut conn, &event, data.game)
+        .await
+        .map_err(|e| ApiError::storage("Failed reading match responses", e))?;
+    let matches = schedule::load(&mut conn, &event)
+        .await
+        .map_err(|e| ApiError::storage("Failed reading schedule", e))?;
+
+    let played: HashSet<i16> = outcomes.iter().map(|o| o.match_number).collect();
+    let runs = forecast.runs.unwrap_or(2_000).clamp(1, 20_000);
+    let mut rng = match forecast.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    let remaining: Vec<&ScheduledMatch> = matches
+        .iter()
+        .filter(|m| !played.contains(&m.match_number))
+        .collect();
+
+    let mut samples: HashMap<u32, TeamSamples> = HashMap::new();
+    for m in &remaining {
+        for (_, _, team) in m.stations() {
+            if let hash_map::Entry::Vacant(slot) = samples.entry(team) {
+                let loaded = TeamSamples::load(&mut conn, team, None, data.game)
+                    .await
+                    .map_err(|e| ApiError::storage("Failed reading match responses", e))?;
+                slot.insert(loaded);
+            }
+        }
+    }
+
+    let alliance =
+        |teams: &[u32]| -> Vec<TeamSamples> { teams.iter().map(|t| samples[t].clone()).collect() };
+    let matchups: Vec<(Vec<TeamSamples>, Vec<TeamSamples>)> = remaining
+        .iter()
+        .map(|m| (alliance(&m.red), alliance(&m.blue)))
+        .collect();
+
+    // a whole schedule of simulations is too slow to run on the executor
+    let game = data.game;
+    let projections = web::block(move || {
+        let mut projections = Vec::new();
+        for (red, blue) in &matchups {
+            let prediction = predict::simulate(red, blue, runs, game, &mut rng);
+            projections.push(Projection {
+                teams: prediction.red.teams,
+                expected_rp: prediction.red.expected_rp,
+            });
+            projections.push(Projection {
+                teams: prediction.blue.teams,
+                expected_rp: prediction.blue.expected_rp,
+            });
+        }
+        Ok::<_, ()>(projections)
+    })
+    .await
+    .map_err(|_| ApiError::Internal("Forecast was cancelled".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(ranking::standings(&outcomes, &projections)))
+}
+
+/// Teams whose latest pit response doesn't match what they've done in matches at the event.
+#[actix_web::get("/events/{event}/discrepancies")]
+pub async fn get_discrepancies(
+    web::Path((event,)): web::Path<(String,)>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let mut conn = data
+        .db
+        .acquire()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
+
+    let responses = query_as::<_, MatchResponse>("SELECT * FROM match_responses WHERE event = ?")
+        .bind(&event)
+        .fetch_all(&mut conn)
+        .await
+        .map_err(|e| ApiError::storage("Failed reading match responses", e))?;
+
+    let mut observed: BTreeMap<u32, Vec<FormIngest>> = BTreeMap::new();
+    for response in consensus::canonical(responses) {
+        observed
+            .entry(response.team_number)
+            .or_default()
+            .push(FormIngest::from(response));
+    }
+
+    let mut found = Vec::new();
+    for (team, matches) in observed {
+        let pit = query_as::<_, PitResponse>(&format!(
+            "SELECT {} FROM pit_responses WHERE team = ? ORDER BY timestamp DESC LIMIT 1",
+            PIT_RESPONSE_COLUMNS
+        ))
+        .bind(team)
+        .fetch_optional(&mut conn)
+        .await
+        .map_err(|e| ApiError::storage("Failed reading pit responses", e))?;
+
+        if let Some(pit) = pit {
+            found.extend(discrepancy::check(team, &pit, &matches));
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(found))
+}
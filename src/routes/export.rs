@@ -0,0 +1,526 @@
+//! Spreadsheet-friendly CSV dumps of the raw responses and the aggregates.
+//!
+//! Column names follow the `FormIngest` fields, so a CSV lines up with what the tablets send.
+
+use actix_web::{http::header::CONTENT_DISPOSITION, web, Error, HttpResponse};
+use serde::{Deserialize, Serialize};
+use specialscout_db::{
+    db::{MatchResponse, PitResponse, TeamDetails, PIT_RESPONSE_COLUMNS},
+    game::{Alliance, DetailedTeam, FormIngest},
+};
+use sqlx::query_as;
+
+use crate::{error::ApiError, AppState};
+
+#[derive(Deserialize)]
+pub struct ExportScope {
+    event: Option<String>,
+}
+
+/// Teams at event `?1`: those on its schedule, and any scouted there without one.
+const EVENT_TEAMS: &str = r#"SELECT team_number FROM match_teams WHERE event = ?1
+    UNION SELECT team_number FROM match_responses WHERE event = ?1"#;
+
+/// `part` out of `whole` as a percentage, left blank when `whole` is 0.
+fn percent(part: i32, whole: i32) -> Option<f32> {
+    (whole != 0).then(|| part as f32 * 100.0 / whole as f32)
+}
+
+fn csv_response<T: Serialize>(filename: &str, rows: &[T]) -> Result<HttpResponse, Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        writer
+            .serialize(row)
+            .map_err(|e| ApiError::internal("Failed writing CSV", e))?;
+    }
+
+    let body = writer
+        .into_inner()
+        .map_err(|e| ApiError::internal("Failed writing CSV", e))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv")
+        .header(
+            CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )
+        .body(body))
+}
+
+#[derive(Serialize)]
+struct MatchRow {
+    id: i64,
+    uuid: u32,
+    timestamp: i32,
+    event: String,
+    match_number: i16,
+    team_number: u32,
+    alliance: Option<Alliance>,
+    did_preload: bool,
+    did_taxi: bool,
+    got_field_cargo: bool,
+    did_hp_shot: bool,
+    did_hp_sink: bool,
+    auto_scored_lower: i16,
+    auto_scored_upper: i16,
+    auto_shots: i16,
+    teleop_scored_lower: i16,
+    teleop_scored_upper: i16,
+    teleop_shots: i16,
+    pins: i16,
+    times_pinned: i16,
+    penalties: i16,
+    climb: i8,
+    performance: i16,
+    comments: String,
+    red_score: i32,
+    blue_score: i32,
+    submission_id: Option<String>,
+    sim_score: i64,
+    auto_accuracy_pct: Option<f32>,
+    teleop_accuracy_pct: Option<f32>,
+}
+
+#[actix_web::get("/export/match_responses.csv")]
+pub async fn export_match_responses(
+    scope: web::Query<ExportScope>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let responses = query_as::<_, MatchResponse>(
+        r#"SELECT * FROM match_responses WHERE (?1 IS NULL OR event = ?1)
+            ORDER BY event, match_number, team_number, id"#,
+    )
+    .bind(&scope.event)
+    .fetch_all(&data.db)
+    .await
+    .map_err(|e| ApiError::storage("Failed reading match responses", e))?;
+
+    let rows: Vec<MatchRow> = responses
+        .into_iter()
+        .map(|r| MatchRow {
+            sim_score: data.game.score(&FormIngest::from(r.clone())),
+            auto_accuracy_pct: percent(
+                (r.auto_scored_lower + r.auto_scored_upper) as i32,
+                r.auto_shots as i32,
+            ),
+            teleop_accuracy_pct: percent(
+                (r.teleop_scored_lower + r.teleop_scored_upper) as i32,
+                r.teleop_shots as i32,
+            ),
+            id: r.id,
+            uuid: r.uuid,
+            timestamp: r.timestamp,
+            event: r.event,
+            match_number: r.match_number,
+            team_number: r.team_number,
+            alliance: r.alliance,
+            did_preload: r.did_preload,
+            did_taxi: r.did_taxi,
+            got_field_cargo: r.got_field_cargo,
+            did_hp_shot: r.did_hp_shot,
+            did_hp_sink: r.did_hp_sink,
+            auto_scored_lower: r.auto_scored_lower,
+            auto_scored_upper: r.auto_scored_upper,
+            auto_shots: r.auto_shots,
+            teleop_scored_lower: r.teleop_scored_lower,
+            teleop_scored_upper: r.teleop_scored_upper,
+            teleop_shots: r.teleop_shots,
+            pins: r.pins,
+            times_pinned: r.times_pinned,
+            penalties: r.penalties,
+            climb: r.climb,
+            performance: r.performance,
+            comments: r.comment,
+            red_score: r.red_score,
+            blue_score: r.blue_score,
+            submission_id: r.submission_id,
+        })
+        .collect();
+
+    csv_response("match_responses.csv", &rows)
+}
+
+#[derive(Serialize)]
+struct PitRow {
+    id: i64,
+    uuid: u32,
+    time_stamp: i32,
+    team_name: String,
+    team_number: i32,
+    drivetrain: String,
+    weight: u16,
+    size_x: f32,
+    size_y: f32,
+    size_z: f32,
+    can_shoot_auto_upper: bool,
+    can_shoot_auto_lower: bool,
+    can_shoot_teleop_upper: bool,
+    can_shoot_teleop_lower: bool,
+    climb: i8,
+    comment: String,
+    build_quality: i16,
+    driver_team: i16,
+    confidence: i16,
+    /// Space separated image ids.
+    images: String,
+}
+
+impl From<PitResponse> for PitRow {
+    fn from(p: PitResponse) -> Self {
+        PitRow {
+            id: p.id,
+            uuid: p.uuid,
+            time_stamp: p.timestamp,
+            team_name: p.team_name,
+            team_number: p.team,
+            drivetrain: p.drivetrain,
+            weight: p.weight,
+            size_x: p.size_x,
+            size_y: p.size_y,
+            size_z: p.size_z,
+            can_shoot_auto_upper: p.can_shoot_auto_upper,
+            can_shoot_auto_lower: p.can_shoot_auto_lower,
+            can_shoot_teleop_upper: p.can_shoot_teleop_upper,
+            can_shoot_teleop_lower: p.can_shoot_teleop_lower,
+            climb: p.climb,
+            comment: p.comment,
+            build_quality: p.build_quality,
+            driver_team: p.driver_team,
+            confidence: p.confidence,
+            images: serde_json::from_str::<Vec<String>>(&p.image_ids)
+                .unwrap_or_default()
+                .join(" "),
+        }
+    }
+}
+
+/// Pit responses aren't tied to an event, so the event filter keeps teams at the event,
+/// whether or not they have been scouted in a match yet.
+#[actix_web::get("/export/pit_responses.csv")]
+pub async fn export_pit_responses(
+    scope: web::Query<ExportScope>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let responses = query_as::<_, PitResponse>(&format!(
+        r#"SELECT {} FROM pit_responses WHERE ?1 IS NULL OR team IN ({})
+            ORDER BY team, timestamp"#,
+        PIT_RESPONSE_COLUMNS, EVENT_TEAMS
+    ))
+    .bind(&scope.event)
+    .fetch_all(&data.db)
+    .await
+    .map_err(|e| ApiError::storage("Failed reading pit responses", e))?;
+
+    let rows: Vec<PitRow> = responses.into_iter().map(PitRow::from).collect();
+
+    csv_response("pit_responses.csv", &rows)
+}
+
+#[derive(Serialize)]
+struct TeamRow {
+    team_number: i32,
+    matches: i32,
+    taxi: i32,
+    taxi_true: i32,
+    preload: i32,
+    auto_shoot: i32,
+    auto_shoot_true: i32,
+    auto_upper_accum: i32,
+    auto_lower_accum: i32,
+    shots_accum: i32,
+    shots_upper_accum: i32,
+    shots_lower_accum: i32,
+    climb: i32,
+    stated_climb: i32,
+    score_accum: i32,
+    matches_won: i32,
+    avg_score: f32,
+    avg_auto_upper: f32,
+    avg_auto_lower: f32,
+    teleop_accuracy_pct: Option<f32>,
+}
+
+impl From<TeamDetails> for TeamRow {
+    fn from(d: TeamDetails) -> Self {
+        let detailed = DetailedTeam::from(d.clone());
+
+        TeamRow {
+            team_number: d.team,
+            matches: d.matches,
+            taxi: d.taxi,
+            taxi_true: d.taxi_true,
+            preload: d.preload,
+            auto_shoot: d.auto_shoot,
+            auto_shoot_true: d.auto_shoot_true,
+            auto_upper_accum: d.auto_upper_accum,
+            auto_lower_accum: d.auto_lower_accum,
+            shots_accum: d.shots_accum,
+            shots_upper_accum: d.shots_upper_accum,
+            shots_lower_accum: d.shots_lower_accum,
+            climb: d.climb,
+            stated_climb: d.stated_climb,
+            score_accum: d.score_accum,
+            matches_won: d.matches_won,
+            avg_score: detailed.avg_score,
+            avg_auto_upper: detailed.avg_auto_upper,
+            avg_auto_lower: detailed.avg_auto_lower,
+            teleop_accuracy_pct: percent(d.shots_upper_accum, d.shots_accum),
+        }
+    }
+}
+
+/// `team_details` isn't split by event, so the event filter keeps teams at the event.
+#[actix_web::get("/export/team_details.csv")]
+pub async fn export_team_details(
+    scope: web::Query<ExportScope>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let details = query_as::<_, TeamDetails>(&format!(
+        "SELECT * FROM team_details WHERE ?1 IS NULL OR team IN ({}) ORDER BY team",
+        EVENT_TEAMS
+    ))
+    .bind(&scope.event)
+    .fetch_all(&data.db)
+    .await
+    .map_err(|e| ApiError::storage("Failed reading team details", e))?;
+
+    let rows: Vec<TeamRow> = details.into_iter().map(TeamRow::from).collect();
+
+    csv_response("team_details.csv", &rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use actix_web::{rt::System, test, App};
+    use serde_json::{json, Value};
+    use specialscout_db::{
+        config::Config,
+        images::ImageStore,
+        migrations,
+        schedule::{self, ScheduledMatch},
+    };
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+
+    fn match_form(event: &str, match_number: i16, team_number: u32) -> FormIngest {
+        serde_json::from_value(json!({
+            "type": "match",
+            "timestamp": match_number,
+            "event": event,
+            "match_number": match_number,
+            "team_number": team_number,
+            "did_preload": true,
+            "did_taxi": true,
+            "got_field_cargo": false,
+            "did_hp_shot": false,
+            "did_hp_sink": false,
+            "auto_scored_lower": 0,
+            "auto_scored_upper": 2,
+            "auto_shots": 2,
+            "teleop_scored_lower": 1,
+            "teleop_scored_upper": 6,
+            "teleop_shots": 10,
+            "pins": 0,
+            "times_pinned": 0,
+            "penalties": 0,
+            "climb": 2,
+            "performance": 7,
+            "comments": "",
+            "red_score": 40,
+            "blue_score": 60,
+            "alliance": "blue",
+            "submission_id": format!("{}-{}-{}", event, match_number, team_number),
+        }))
+        .unwrap()
+    }
+
+    fn pit_form(team_number: i32) -> FormIngest {
+        serde_json::from_value(json!({
+            "type": "pit",
+            "time_stamp": 1,
+            "team_name": format!("Team {}", team_number),
+            "team_number": team_number,
+            "drivetrain": "swerve",
+            "weight": 110,
+            "size": { "x": 30.0, "y": 30.0, "z": 40.0 },
+            "can_shoot_auto_upper": true,
+            "can_shoot_auto_lower": false,
+            "can_shoot_teleop_upper": true,
+            "can_shoot_teleop_lower": false,
+            "climb": 2,
+            "comment": "",
+            "build_quality": 7,
+            "driver_team": 7,
+            "confidence": 7,
+            "submission_id": format!("pit-{}", team_number),
+        }))
+        .unwrap()
+    }
+
+    /// Field names the tablets send for `form`.
+    fn form_fields(form: &FormIngest) -> BTreeSet<String> {
+        match serde_json::to_value(form).unwrap() {
+            Value::Object(fields) => fields
+                .into_iter()
+                .map(|(k, _)| k)
+                .filter(|k| k != "type")
+                .collect(),
+            _ => panic!("forms are sent as objects"),
+        }
+    }
+
+    fn names(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    /// Event 2022a has team 1 on its schedule but not yet scouted, and team 4 scouted.
+    /// Team 9 has only played at 2022b. All three have pit responses.
+    async fn state() -> AppState {
+        let db = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let mut conn = db.acquire().await.unwrap();
+        migrations::migrate(&mut conn).await.unwrap();
+
+        let config = Config::default();
+        let game = config.game();
+        let images =
+            ImageStore::open(std::env::temp_dir().join("specialscout-export-tests")).unwrap();
+
+        let scheduled = ScheduledMatch {
+            match_number: 1,
+            red: vec![1, 2, 3],
+            blue: vec![4, 5, 6],
+        };
+        schedule::store(&mut conn, "2022a", &[scheduled])
+            .await
+            .unwrap();
+
+        let forms = [
+            match_form("2022a", 1, 4),
+            match_form("2022b", 1, 9),
+            pit_form(1),
+            pit_form(4),
+            pit_form(9),
+        ];
+        for form in &forms {
+            crate::insert_response(form, 1, game, &images, &mut conn)
+                .await
+                .unwrap();
+        }
+        drop(conn);
+
+        AppState {
+            db,
+            game,
+            images,
+            config,
+        }
+    }
+
+    /// The header row and the rows of a CSV export.
+    fn export(uri: &str) -> (Vec<String>, Vec<Vec<String>>) {
+        let uri = uri.to_string();
+        System::new("export-tests").block_on(async move {
+            let mut app = test::init_service(
+                App::new()
+                    .data(state().await)
+                    .service(export_match_responses)
+                    .service(export_pit_responses)
+                    .service(export_team_details),
+            )
+            .await;
+            let response =
+                test::call_service(&mut app, test::TestRequest::get().uri(&uri).to_request()).await;
+            assert!(response.status().is_success());
+            assert_eq!(response.headers().get("content-type").unwrap(), "text/csv");
+            let body = test::read_body(response).await;
+
+            let mut reader = csv::Reader::from_reader(&body[..]);
+            let headers = reader
+                .headers()
+                .unwrap()
+                .iter()
+                .map(str::to_string)
+                .collect();
+            let rows = reader
+                .records()
+                .map(|r| r.unwrap().iter().map(str::to_string).collect())
+                .collect();
+            (headers, rows)
+        })
+    }
+
+    /// The values of column `name`, in row order.
+    fn column(export: &(Vec<String>, Vec<Vec<String>>), name: &str) -> Vec<String> {
+        let (headers, rows) = export;
+        let i = headers.iter().position(|h| h == name).unwrap();
+        rows.iter().map(|row| row[i].clone()).collect()
+    }
+
+    #[test]
+    fn match_headers_follow_the_form() {
+        let (headers, _) = export("/export/match_responses.csv");
+        let headers: BTreeSet<String> = headers.into_iter().collect();
+
+        let form = form_fields(&match_form("2022a", 1, 4));
+        let extra = names(&[
+            "id",
+            "uuid",
+            "sim_score",
+            "auto_accuracy_pct",
+            "teleop_accuracy_pct",
+        ]);
+        assert_eq!(headers, form.union(&extra).cloned().collect());
+    }
+
+    #[test]
+    fn pit_headers_follow_the_form() {
+        let (headers, _) = export("/export/pit_responses.csv");
+        let headers: BTreeSet<String> = headers.into_iter().collect();
+
+        // the size is split into columns, and legacy pictures are stored as images
+        let form = &form_fields(&pit_form(1)) - &names(&["size", "picture", "submission_id"]);
+        let extra = names(&["id", "uuid", "size_x", "size_y", "size_z"]);
+        assert_eq!(headers, form.union(&extra).cloned().collect());
+    }
+
+    #[test]
+    fn match_export_filters_by_event() {
+        let all = export("/export/match_responses.csv");
+        assert_eq!(column(&all, "team_number"), vec!["4", "9"]);
+
+        let event = export("/export/match_responses.csv?event=2022a");
+        assert_eq!(column(&event, "team_number"), vec!["4"]);
+        assert_eq!(column(&event, "auto_accuracy_pct"), vec!["100.0"]);
+        assert_eq!(column(&event, "teleop_accuracy_pct"), vec!["70.0"]);
+    }
+
+    #[test]
+    fn pit_export_keeps_scheduled_teams_without_matches() {
+        let all = export("/export/pit_responses.csv");
+        assert_eq!(column(&all, "team_number"), vec!["1", "4", "9"]);
+
+        let event = export("/export/pit_responses.csv?event=2022a");
+        assert_eq!(column(&event, "team_number"), vec!["1", "4"]);
+        assert_eq!(column(&event, "size_x"), vec!["30.0", "30.0"]);
+    }
+
+    #[test]
+    fn team_export_keeps_scheduled_teams_without_matches() {
+        let all = export("/export/team_details.csv");
+        assert_eq!(column(&all, "team_number"), vec!["1", "4", "9"]);
+
+        let event = export("/export/team_details.csv?event=2022a");
+        assert_eq!(column(&event, "team_number"), vec!["1", "4"]);
+        assert_eq!(column(&event, "matches"), vec!["0", "1"]);
+        assert_eq!(column(&event, "matches_won"), vec!["0", "1"]);
+    }
+}
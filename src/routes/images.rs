@@ -0,0 +1,69 @@
+use std::{fs, io, path::PathBuf};
+
+use actix_web::{
+    error::BlockingError,
+    http::header::{CACHE_CONTROL, ETAG, IF_NONE_MATCH},
+    web, Error, HttpRequest, HttpResponse,
+};
+use specialscout_db::images::{sniff_content_type, ImageStore};
+
+use crate::{error::ApiError, AppState};
+
+/// Sends a stored image. Ids name their contents, so clients may cache them forever and
+/// revalidating just means comparing the id.
+pub async fn serve(req: &HttpRequest, id: &str, path: PathBuf) -> Result<HttpResponse, Error> {
+    let etag = format!("\"{}\"", id);
+
+    let cached = req
+        .headers()
+        .get(IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .into_iter()
+        .flat_map(|v| v.split(','))
+        .any(|tag| tag.trim() == etag);
+    if cached {
+        return Ok(HttpResponse::NotModified().header(ETAG, etag).finish());
+    }
+
+    let bytes = match web::block(move || fs::read(path)).await {
+        Ok(bytes) => bytes,
+        Err(BlockingError::Error(e)) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(ApiError::NotFound(format!("No image {}", id)).into())
+        }
+        Err(e) => return Err(ApiError::internal("Failed reading image", e).into()),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(sniff_content_type(&bytes))
+        .header(CACHE_CONTROL, "public, max-age=31536000, immutable")
+        .header(ETAG, etag)
+        .body(bytes))
+}
+
+#[actix_web::get("/images/{id}")]
+pub async fn get_image(
+    web::Path((id,)): web::Path<(String,)>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    if !ImageStore::is_valid_id(&id) {
+        return Err(ApiError::NotFound(format!("No image {}", id)).into());
+    }
+
+    let path = data.images.path(&id);
+    serve(&req, &id, path).await
+}
+
+#[actix_web::get("/images/{id}/thumbnail")]
+pub async fn get_thumbnail(
+    web::Path((id,)): web::Path<(String,)>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    if !ImageStore::is_valid_id(&id) {
+        return Err(ApiError::NotFound(format!("No image {}", id)).into());
+    }
+
+    let path = data.images.thumbnail_path(&id);
+    serve(&req, &format!("{}-thumb", id), path).await
+}
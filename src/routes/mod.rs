@@ -0,0 +1,9 @@
+pub mod admin;
+pub mod auth;
+pub mod events;
+pub mod export;
+pub mod images;
+pub mod predict;
+pub mod responses;
+pub mod scouts;
+pub mod teams;
@@ -0,0 +1,79 @@
+use actix_web::{web, Error, HttpResponse};
+use rand::{rngs::StdRng, SeedableRng};
+use serde::Deserialize;
+use specialscout_db::predict::{simulate, TeamSamples};
+
+use crate::{error::ApiError, AppState};
+
+const DEFAULT_RUNS: usize = 10_000;
+const MAX_RUNS: usize = 100_000;
+
+#[derive(Deserialize)]
+pub struct Matchup {
+    /// Comma separated team numbers.
+    red: String,
+    blue: String,
+    /// Only draw from matches played at this event.
+    event: Option<String>,
+    runs: Option<usize>,
+    /// Makes the simulation repeatable.
+    seed: Option<u64>,
+}
+
+fn parse_teams(teams: &str) -> Result<Vec<u32>, String> {
+    let parsed = teams
+        .split(',')
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(|t| t.parse().map_err(|_| format!("{} is not a team number", t)))
+        .collect::<Result<Vec<u32>, _>>()?;
+
+    if parsed.is_empty() || parsed.len() > 3 {
+        return Err(format!("expected 1 to 3 teams, got {}", parsed.len()));
+    }
+
+    Ok(parsed)
+}
+
+/// Predicts an upcoming match by simulating it from every robot's observed matches.
+#[actix_web::get("/predict")]
+pub async fn predict(
+    matchup: web::Query<Matchup>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let (red, blue) = match (parse_teams(&matchup.red), parse_teams(&matchup.blue)) {
+        (Ok(red), Ok(blue)) => (red, blue),
+        (Err(reason), _) | (_, Err(reason)) => return Err(ApiError::Invalid(reason).into()),
+    };
+
+    let mut conn = data
+        .db
+        .acquire()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
+
+    let mut alliances = (Vec::new(), Vec::new());
+    for (teams, samples) in [(&red, &mut alliances.0), (&blue, &mut alliances.1)] {
+        for &team in teams {
+            let team = TeamSamples::load(&mut conn, team, matchup.event.as_deref(), data.game)
+                .await
+                .map_err(|e| ApiError::storage("Failed reading match responses", e))?;
+            samples.push(team);
+        }
+    }
+
+    let runs = matchup.runs.unwrap_or(DEFAULT_RUNS).clamp(1, MAX_RUNS);
+    let mut rng = match matchup.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    // up to MAX_RUNS simulations would hold up every other request on this worker
+    let game = data.game;
+    let prediction =
+        web::block(move || Ok::<_, ()>(simulate(&alliances.0, &alliances.1, runs, game, &mut rng)))
+            .await
+            .map_err(|_| ApiError::Internal("Prediction was cancelled".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(prediction))
+}
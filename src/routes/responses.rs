@@ -0,0 +1,436 @@
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+use specialscout_db::{
+    aggregate,
+    audit::{self, Action},
+    db::{MatchResponse, PitResponse, PIT_RESPONSE_COLUMNS},
+    game::FormIngest,
+    validation,
+};
+use sqlx::{query, query_as, SqliteConnection};
+
+use crate::{
+    error::ApiError,
+    routes::{self, auth::identify},
+    AppState,
+};
+
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+
+/// Query string filters for `GET /responses/match`. Every filter is optional.
+#[derive(Deserialize)]
+pub struct MatchResponseFilter {
+    event: Option<String>,
+    team_number: Option<u32>,
+    match_number: Option<i16>,
+    uuid: Option<u32>,
+    /// Inclusive lower bound on the response timestamp.
+    since: Option<i32>,
+    /// Inclusive upper bound on the response timestamp.
+    until: Option<i32>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+}
+
+/// A stored match response along with the scout that submitted it.
+#[derive(Serialize)]
+struct ScoutedResponse {
+    id: i64,
+    uuid: u32,
+    #[serde(flatten)]
+    response: FormIngest,
+}
+
+#[actix_web::get("/responses/match")]
+pub async fn get_match_responses(
+    filter: web::Query<MatchResponseFilter>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let limit = filter.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let offset = filter.offset.unwrap_or(0);
+
+    let rows = query_as::<_, MatchResponse>(
+        r#"SELECT * FROM match_responses
+            WHERE (?1 IS NULL OR event = ?1)
+            AND (?2 IS NULL OR team_number = ?2)
+            AND (?3 IS NULL OR match_number = ?3)
+            AND (?4 IS NULL OR uuid = ?4)
+            AND (?5 IS NULL OR timestamp >= ?5)
+            AND (?6 IS NULL OR timestamp <= ?6)
+            ORDER BY timestamp, match_number, team_number
+            LIMIT ?7 OFFSET ?8"#,
+    )
+    .bind(&filter.event)
+    .bind(filter.team_number)
+    .bind(filter.match_number)
+    .bind(filter.uuid)
+    .bind(filter.since)
+    .bind(filter.until)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(&data.db)
+    .await
+    .map_err(|e| ApiError::storage("Failed reading match responses", e))?;
+
+    let responses: Vec<ScoutedResponse> = rows
+        .into_iter()
+        .map(|row| ScoutedResponse {
+            id: row.id,
+            uuid: row.uuid,
+            response: row.into(),
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(responses))
+}
+
+/// Overwrites the fields named in `patch` on a copy of `row`. Fields are the table's column
+/// names; unknown fields and the `id` are rejected.
+fn apply_patch<T: Serialize + DeserializeOwned>(
+    row: &T,
+    patch: Map<String, Value>,
+) -> Result<(T, Value, Value), String> {
+    let before = serde_json::to_value(row).map_err(|e| e.to_string())?;
+    let mut after = before.clone();
+    let fields = after.as_object_mut().expect("rows serialize to objects");
+
+    for (key, value) in patch {
+        if key == "id" {
+            return Err(String::from("id can't be changed"));
+        }
+        match fields.get_mut(&key) {
+            Some(field) => *field = value,
+            None => return Err(format!("unknown field {}", key)),
+        }
+    }
+
+    let patched = serde_json::from_value(after.clone()).map_err(|e| e.to_string())?;
+    Ok((patched, before, after))
+}
+
+async fn fetch_match_response(
+    conn: &mut SqliteConnection,
+    id: i64,
+) -> Result<MatchResponse, Error> {
+    query_as::<_, MatchResponse>("SELECT * FROM match_responses WHERE id = ?")
+        .bind(id)
+        .fetch_optional(conn)
+        .await
+        .map_err(|e| ApiError::storage("Failed reading match response", e))?
+        .ok_or_else(|| ApiError::NotFound(format!("No match response {}", id)).into())
+}
+
+async fn fetch_pit_response(conn: &mut SqliteConnection, id: i64) -> Result<PitResponse, Error> {
+    query_as::<_, PitResponse>(&format!(
+        "SELECT {} FROM pit_responses WHERE id = ?",
+        PIT_RESPONSE_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(conn)
+    .await
+    .map_err(|e| ApiError::storage("Failed reading pit response", e))?
+    .ok_or_else(|| ApiError::NotFound(format!("No pit response {}", id)).into())
+}
+
+/// Checks a corrected row against the same rules as a new submission. Only errors stop the
+/// correction; warnings are dropped.
+async fn check_patched(
+    conn: &mut SqliteConnection,
+    data: &AppState,
+    form: FormIngest,
+) -> Result<(), Error> {
+    let known_events =
+        validation::known_events(conn, data.config.event.as_deref(), &data.config.validation)
+            .await
+            .map_err(|e| ApiError::storage("Failed reading events", e))?;
+    crate::validate(&form, data, &known_events)?;
+    Ok(())
+}
+
+/// Rebuilds the details of the team a response was filed under, and of the team it was
+/// moved to if that changed.
+async fn rebuild_teams(
+    conn: &mut SqliteConnection,
+    data: &AppState,
+    before: i32,
+    after: i32,
+) -> Result<(), Error> {
+    aggregate::rebuild_team(conn, before, data.game)
+        .await
+        .map_err(|e| ApiError::storage("Failed rebuilding team details", e))?;
+
+    if after != before {
+        aggregate::rebuild_team(conn, after, data.game)
+            .await
+            .map_err(|e| ApiError::storage("Failed rebuilding team details", e))?;
+    }
+
+    Ok(())
+}
+
+#[actix_web::patch("/responses/match/{id}")]
+pub async fn patch_match_response(
+    web::Path((id,)): web::Path<(i64,)>,
+    req: HttpRequest,
+    patch: web::Json<Map<String, Value>>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let caller = identify(&req, &data).await?;
+
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
+
+    let row = fetch_match_response(&mut tx, id).await?;
+    caller.may_change(row.uuid)?;
+    let (row, before, after) = apply_patch(&row, patch.into_inner()).map_err(ApiError::Invalid)?;
+    // nor can a scout hand their response to somebody else
+    caller.may_change(row.uuid)?;
+    let old_team = before["team_number"].as_i64().unwrap_or_default() as i32;
+    check_patched(&mut tx, &data, FormIngest::from(row.clone())).await?;
+
+    query(
+        r#"UPDATE match_responses SET
+            timestamp = ?, uuid = ?, event = ?, team_number = ?, match_number = ?, did_preload = ?,
+            did_taxi = ?, got_field_cargo = ?, did_hp_shot = ?, did_hp_sink = ?,
+            auto_scored_lower = ?, auto_scored_upper = ?, auto_shots = ?, teleop_scored_lower = ?,
+            teleop_scored_upper = ?, teleop_shots = ?, pins = ?, times_pinned = ?, penalties = ?,
+            performance = ?, red_score = ?, blue_score = ?, climb = ?, comment = ?, alliance = ?,
+            submission_id = ?
+            WHERE id = ?"#,
+    )
+    .bind(row.timestamp)
+    .bind(row.uuid)
+    .bind(&row.event)
+    .bind(row.team_number)
+    .bind(row.match_number)
+    .bind(row.did_preload)
+    .bind(row.did_taxi)
+    .bind(row.got_field_cargo)
+    .bind(row.did_hp_shot)
+    .bind(row.did_hp_sink)
+    .bind(row.auto_scored_lower)
+    .bind(row.auto_scored_upper)
+    .bind(row.auto_shots)
+    .bind(row.teleop_scored_lower)
+    .bind(row.teleop_scored_upper)
+    .bind(row.teleop_shots)
+    .bind(row.pins)
+    .bind(row.times_pinned)
+    .bind(row.penalties)
+    .bind(row.performance)
+    .bind(row.red_score)
+    .bind(row.blue_score)
+    .bind(row.climb)
+    .bind(&row.comment)
+    .bind(row.alliance)
+    .bind(&row.submission_id)
+    .bind(id)
+    .execute(&mut tx)
+    .await
+    .map_err(|e| ApiError::storage("Failed updating match response", e))?;
+
+    audit::record(
+        &mut tx,
+        &caller.to_string(),
+        Action::Update,
+        "match_responses",
+        id,
+        Some(&before),
+        Some(&after),
+    )
+    .await
+    .map_err(|e| ApiError::storage("Failed writing audit log", e))?;
+
+    rebuild_teams(&mut tx, &data, old_team, row.team_number as i32).await?;
+
+    tx.commit()
+        .await
+        .map_err(|e| ApiError::storage("Failed committing correction", e))?;
+
+    Ok(HttpResponse::Ok().json(ScoutedResponse {
+        id: row.id,
+        uuid: row.uuid,
+        response: row.into(),
+    }))
+}
+
+#[actix_web::delete("/responses/match/{id}")]
+pub async fn delete_match_response(
+    web::Path((id,)): web::Path<(i64,)>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let caller = identify(&req, &data).await?;
+
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
+
+    let row = fetch_match_response(&mut tx, id).await?;
+    caller.may_change(row.uuid)?;
+    let before = serde_json::to_value(&row)?;
+
+    query("DELETE FROM match_responses WHERE id = ?")
+        .bind(id)
+        .execute(&mut tx)
+        .await
+        .map_err(|e| ApiError::storage("Failed deleting match response", e))?;
+
+    audit::record(
+        &mut tx,
+        &caller.to_string(),
+        Action::Delete,
+        "match_responses",
+        id,
+        Some(&before),
+        None,
+    )
+    .await
+    .map_err(|e| ApiError::storage("Failed writing audit log", e))?;
+
+    rebuild_teams(
+        &mut tx,
+        &data,
+        row.team_number as i32,
+        row.team_number as i32,
+    )
+    .await?;
+
+    tx.commit()
+        .await
+        .map_err(|e| ApiError::storage("Failed committing deletion", e))?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[actix_web::patch("/responses/pit/{id}")]
+pub async fn patch_pit_response(
+    web::Path((id,)): web::Path<(i64,)>,
+    req: HttpRequest,
+    patch: web::Json<Map<String, Value>>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let caller = identify(&req, &data).await?;
+
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
+
+    let row = fetch_pit_response(&mut tx, id).await?;
+    caller.may_change(row.uuid)?;
+    let (row, before, after) = apply_patch(&row, patch.into_inner()).map_err(ApiError::Invalid)?;
+    // nor can a scout hand their response to somebody else
+    caller.may_change(row.uuid)?;
+    let old_team = before["team"].as_i64().unwrap_or_default() as i32;
+    check_patched(&mut tx, &data, FormIngest::from(row.clone())).await?;
+
+    let image_ids: Vec<String> = serde_json::from_str(&row.image_ids)
+        .map_err(|_| ApiError::Invalid("image_ids must be a JSON list of ids".to_string()))?;
+    routes::teams::check_team_images(&mut tx, row.team as u32, &image_ids).await?;
+
+    query(
+        r#"UPDATE pit_responses SET
+            timestamp = ?, uuid = ?, team = ?, team_name = ?, drivetrain = ?, weight = ?,
+            size_x = ?, size_y = ?, size_z = ?, can_shoot_auto_upper = ?, can_shoot_auto_lower = ?,
+            can_shoot_teleop_upper = ?, can_shoot_teleop_lower = ?, climb = ?, build_quality = ?,
+            confidence = ?, driver_team = ?, comment = ?, image_ids = ?
+            WHERE id = ?"#,
+    )
+    .bind(row.timestamp)
+    .bind(row.uuid)
+    .bind(row.team)
+    .bind(&row.team_name)
+    .bind(&row.drivetrain)
+    .bind(row.weight)
+    .bind(row.size_x)
+    .bind(row.size_y)
+    .bind(row.size_z)
+    .bind(row.can_shoot_auto_upper)
+    .bind(row.can_shoot_auto_lower)
+    .bind(row.can_shoot_teleop_upper)
+    .bind(row.can_shoot_teleop_lower)
+    .bind(row.climb)
+    .bind(row.build_quality)
+    .bind(row.confidence)
+    .bind(row.driver_team)
+    .bind(&row.comment)
+    .bind(&row.image_ids)
+    .bind(id)
+    .execute(&mut tx)
+    .await
+    .map_err(|e| ApiError::storage("Failed updating pit response", e))?;
+
+    audit::record(
+        &mut tx,
+        &caller.to_string(),
+        Action::Update,
+        "pit_responses",
+        id,
+        Some(&before),
+        Some(&after),
+    )
+    .await
+    .map_err(|e| ApiError::storage("Failed writing audit log", e))?;
+
+    rebuild_teams(&mut tx, &data, old_team, row.team).await?;
+
+    tx.commit()
+        .await
+        .map_err(|e| ApiError::storage("Failed committing correction", e))?;
+
+    Ok(HttpResponse::Ok().json(row))
+}
+
+#[actix_web::delete("/responses/pit/{id}")]
+pub async fn delete_pit_response(
+    web::Path((id,)): web::Path<(i64,)>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let caller = identify(&req, &data).await?;
+
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
+
+    let row = fetch_pit_response(&mut tx, id).await?;
+    caller.may_change(row.uuid)?;
+    let before = serde_json::to_value(&row)?;
+
+    query("DELETE FROM pit_responses WHERE id = ?")
+        .bind(id)
+        .execute(&mut tx)
+        .await
+        .map_err(|e| ApiError::storage("Failed deleting pit response", e))?;
+
+    audit::record(
+        &mut tx,
+        &caller.to_string(),
+        Action::Delete,
+        "pit_responses",
+        id,
+        Some(&before),
+        None,
+    )
+    .await
+    .map_err(|e| ApiError::storage("Failed writing audit log", e))?;
+
+    rebuild_teams(&mut tx, &data, row.team, row.team).await?;
+
+    tx.commit()
+        .await
+        .map_err(|e| ApiError::storage("Failed committing deletion", e))?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
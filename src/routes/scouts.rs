@@ -0,0 +1,205 @@
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use specialscout_db::{
+    schedule::Assignment,
+    scouts::{generate_token, hash_token, ScoutStats},
+};
+use sqlx::{query, query_as, query_scalar, SqlitePool};
+
+use crate::{
+    error::ApiError,
+    routes::auth::{bearer_token, identify, require_admin, Caller},
+    AppState,
+};
+
+#[derive(Deserialize)]
+pub struct Registration {
+    /// The uuid the tablet already uses, if any. One is assigned otherwise.
+    uuid: Option<u32>,
+    name: String,
+    team_number: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct IssuedToken {
+    uuid: u32,
+    token: String,
+}
+
+const SCOUT_STATS: &str = r#"SELECT u.uuid, u.name, u.team_number,
+    (SELECT COUNT(*) FROM match_responses m WHERE m.uuid = u.uuid) AS match_responses,
+    (SELECT COUNT(*) FROM pit_responses p WHERE p.uuid = u.uuid) AS pit_responses,
+    (SELECT COUNT(*) FROM (
+        SELECT team_number FROM match_responses m WHERE m.uuid = u.uuid
+        UNION SELECT team FROM pit_responses p WHERE p.uuid = u.uuid
+    )) AS teams_covered,
+    (SELECT MAX(t) FROM (
+        SELECT MAX(timestamp) AS t FROM match_responses m WHERE m.uuid = u.uuid
+        UNION ALL SELECT MAX(timestamp) FROM pit_responses p WHERE p.uuid = u.uuid
+    )) AS last_submission
+    FROM uuids u WHERE (?1 IS NULL OR u.uuid = ?1) ORDER BY u.uuid"#;
+
+/// Checks that the request carries the token issued to scout `uuid`, as
+/// `Authorization: Bearer <token>`.
+pub async fn authenticate(req: &HttpRequest, uuid: u32, db: &SqlitePool) -> Result<(), Error> {
+    let token = bearer_token(req)
+        .ok_or_else(|| ApiError::Unauthorized("Missing scout token".to_string()))?;
+
+    let owner: Option<u32> = query_scalar("SELECT uuid FROM uuids WHERE token_hash = ?")
+        .bind(hash_token(token))
+        .fetch_optional(db)
+        .await
+        .map_err(|e| ApiError::storage("Failed checking scout token", e))?;
+
+    match owner {
+        Some(owner) if owner == uuid => Ok(()),
+        Some(_) => {
+            Err(ApiError::Forbidden(format!("Token does not belong to scout {}", uuid)).into())
+        }
+        None => Err(ApiError::Unauthorized("Unknown scout token".to_string()).into()),
+    }
+}
+
+/// Registers a scout and issues their first token. Admin only, since a token lets its holder
+/// submit data.
+#[actix_web::post("/scouts")]
+pub async fn register_scout(
+    registration: web::Json<Registration>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require_admin(&req, &data)?;
+
+    let token = generate_token();
+
+    let result =
+        query("INSERT INTO uuids (uuid, name, team_number, token_hash) VALUES (?, ?, ?, ?)")
+            .bind(registration.uuid)
+            .bind(&registration.name)
+            .bind(registration.team_number)
+            .bind(hash_token(&token))
+            .execute(&data.db)
+            .await;
+
+    let uuid = match result {
+        Ok(done) => done.last_insert_rowid() as u32,
+        Err(sqlx::Error::Database(e)) if e.message().contains("UNIQUE") => {
+            return Err(ApiError::Duplicate("That uuid is already registered".to_string()).into());
+        }
+        Err(e) => return Err(ApiError::storage("Failed registering scout", e).into()),
+    };
+
+    Ok(HttpResponse::Created().json(IssuedToken { uuid, token }))
+}
+
+/// Issues a new token for a scout, revoking the old one. Needs the admin token or the
+/// scout's current one.
+#[actix_web::post("/scouts/{uuid}/token")]
+pub async fn reissue_token(
+    web::Path((uuid,)): web::Path<(u32,)>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    match identify(&req, &data).await? {
+        Caller::Admin => {}
+        Caller::Scout(caller) if caller == uuid => {}
+        Caller::Scout(_) => {
+            return Err(
+                ApiError::Forbidden(format!("Token does not belong to scout {}", uuid)).into(),
+            )
+        }
+    }
+
+    let token = generate_token();
+
+    let done = query("UPDATE uuids SET token_hash = ? WHERE uuid = ?")
+        .bind(hash_token(&token))
+        .bind(uuid)
+        .execute(&data.db)
+        .await
+        .map_err(|e| ApiError::storage("Failed issuing token", e))?;
+
+    if done.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("No scout {}", uuid)).into());
+    }
+
+    Ok(HttpResponse::Ok().json(IssuedToken { uuid, token }))
+}
+
+#[actix_web::get("/scouts")]
+pub async fn get_scouts(data: web::Data<AppState>) -> Result<HttpResponse, Error> {
+    let scouts = query_as::<_, ScoutStats>(SCOUT_STATS)
+        .bind(Option::<u32>::None)
+        .fetch_all(&data.db)
+        .await
+        .map_err(|e| ApiError::storage("Failed reading scouts", e))?;
+
+    Ok(HttpResponse::Ok().json(scouts))
+}
+
+#[actix_web::get("/scouts/{uuid}")]
+pub async fn get_scout(
+    web::Path((uuid,)): web::Path<(u32,)>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let scout = query_as::<_, ScoutStats>(SCOUT_STATS)
+        .bind(uuid)
+        .fetch_optional(&data.db)
+        .await
+        .map_err(|e| ApiError::storage("Failed reading scout", e))?;
+
+    match scout {
+        Some(scout) => Ok(HttpResponse::Ok().json(scout)),
+        None => Err(ApiError::NotFound(format!("No scout {}", uuid)).into()),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct NextAssignment {
+    /// Defaults to the active event.
+    event: Option<String>,
+    /// Skip matches up to and including this one.
+    after: Option<i16>,
+}
+
+/// The next match and robot a scout is assigned at an event. Matches the scout has already
+/// submitted a response for, or anything before them, are skipped.
+#[actix_web::get("/scouts/{uuid}/assignment")]
+pub async fn get_assignment(
+    web::Path((uuid,)): web::Path<(u32,)>,
+    params: web::Query<NextAssignment>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let event = match params.event.as_ref().or(data.config.event.as_ref()) {
+        Some(event) => event,
+        None => {
+            return Err(ApiError::Invalid("No event given and none is active".to_string()).into())
+        }
+    };
+
+    let assignment = query_as::<_, Assignment>(
+        r#"SELECT match_number, uuid, alliance, station, team_number FROM assignments a
+            WHERE a.event = ?1 AND a.uuid = ?2
+            AND a.match_number > MAX(
+                COALESCE(?3, 0),
+                COALESCE((SELECT MAX(match_number) FROM match_responses r
+                    WHERE r.event = ?1 AND r.uuid = ?2), 0)
+            )
+            ORDER BY a.match_number LIMIT 1"#,
+    )
+    .bind(event)
+    .bind(uuid)
+    .bind(params.after)
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|e| ApiError::storage("Failed reading assignment", e))?;
+
+    match assignment {
+        Some(assignment) => Ok(HttpResponse::Ok().json(assignment)),
+        None => Err(ApiError::NotFound(format!(
+            "No upcoming assignment for scout {} at {}",
+            uuid, event
+        ))
+        .into()),
+    }
+}
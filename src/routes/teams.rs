@@ -0,0 +1,382 @@
+use actix_multipart::Multipart;
+use actix_web::{error::BlockingError, web, Error, HttpRequest, HttpResponse};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use specialscout_db::{
+    db::{PitResponse, TeamDetails, PIT_RESPONSE_COLUMNS},
+    game::{DetailedTeam, Size},
+    images::{decode_picture, ImageError, ImageStore, StoredImage},
+    ranking, stats,
+};
+use sqlx::{query, query_as, query_scalar, SqliteConnection};
+
+use crate::{error::ApiError, routes, AppState};
+
+/// Details with the ranking points each team has earned filled in.
+async fn with_rp(
+    details: Vec<TeamDetails>,
+    data: &AppState,
+) -> Result<Vec<DetailedTeam>, ApiError> {
+    let mut conn = data
+        .db
+        .acquire()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
+
+    let earned = ranking::earned_rp(&mut conn, data.game)
+        .await
+        .map_err(|e| ApiError::storage("Failed computing ranking points", e))?;
+
+    Ok(details
+        .into_iter()
+        .map(|details| {
+            let mut team = DetailedTeam::from(details);
+            team.rp = earned
+                .get(&(team.team.number() as u32))
+                .copied()
+                .unwrap_or(0) as i16;
+            team
+        })
+        .collect())
+}
+
+#[actix_web::get("/teams")]
+pub async fn get_teams(data: web::Data<AppState>) -> Result<HttpResponse, Error> {
+    let details = query_as::<_, TeamDetails>("SELECT * FROM team_details ORDER BY team")
+        .fetch_all(&data.db)
+        .await
+        .map_err(|e| ApiError::storage("Failed reading team details", e))?;
+
+    let teams = with_rp(details, &data).await?;
+
+    Ok(HttpResponse::Ok().json(teams))
+}
+
+#[actix_web::get("/teams/{number}")]
+pub async fn get_team(
+    web::Path((number,)): web::Path<(u32,)>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let details = query_as::<_, TeamDetails>("SELECT * FROM team_details WHERE team = ?")
+        .bind(number)
+        .fetch_optional(&data.db)
+        .await
+        .map_err(|e| ApiError::storage("Failed reading team details", e))?;
+
+    match details {
+        Some(details) => Ok(HttpResponse::Ok().json(with_rp(vec![details], &data).await?.pop())),
+        None => Err(ApiError::NotFound(format!("No data for team {}", number)).into()),
+    }
+}
+
+/// A single pit scouting response, without the picture.
+#[derive(Serialize, Clone)]
+struct PitProfile {
+    id: i64,
+    timestamp: i32,
+    uuid: u32,
+    team_number: i32,
+    team_name: String,
+    drivetrain: String,
+    weight: u16,
+    size: Size,
+    can_shoot_auto_upper: bool,
+    can_shoot_auto_lower: bool,
+    can_shoot_teleop_upper: bool,
+    can_shoot_teleop_lower: bool,
+    climb: i8,
+    build_quality: i16,
+    driver_team: i16,
+    confidence: i16,
+    comment: String,
+    /// Ids of the photos attached to this response.
+    images: Vec<String>,
+}
+
+impl From<PitResponse> for PitProfile {
+    fn from(resp: PitResponse) -> Self {
+        PitProfile {
+            id: resp.id,
+            timestamp: resp.timestamp,
+            uuid: resp.uuid,
+            team_number: resp.team,
+            team_name: resp.team_name,
+            drivetrain: resp.drivetrain,
+            weight: resp.weight,
+            size: Size {
+                x: resp.size_x,
+                y: resp.size_y,
+                z: resp.size_z,
+            },
+            can_shoot_auto_upper: resp.can_shoot_auto_upper,
+            can_shoot_auto_lower: resp.can_shoot_auto_lower,
+            can_shoot_teleop_upper: resp.can_shoot_teleop_upper,
+            can_shoot_teleop_lower: resp.can_shoot_teleop_lower,
+            climb: resp.climb,
+            build_quality: resp.build_quality,
+            driver_team: resp.driver_team,
+            confidence: resp.confidence,
+            comment: resp.comment,
+            images: serde_json::from_str(&resp.image_ids).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct PitHistory {
+    latest: PitProfile,
+    /// Every pit response for the team, newest first. Includes `latest`.
+    history: Vec<PitProfile>,
+}
+
+#[actix_web::get("/teams/{number}/pit")]
+pub async fn get_team_pit(
+    web::Path((number,)): web::Path<(u32,)>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let rows = query_as::<_, PitResponse>(&format!(
+        "SELECT {} FROM pit_responses WHERE team = ? ORDER BY timestamp DESC",
+        PIT_RESPONSE_COLUMNS
+    ))
+    .bind(number)
+    .fetch_all(&data.db)
+    .await
+    .map_err(|e| ApiError::storage("Failed reading pit responses", e))?;
+
+    let history: Vec<PitProfile> = rows.into_iter().map(PitProfile::from).collect();
+
+    match history.first() {
+        Some(latest) => Ok(HttpResponse::Ok().json(PitHistory {
+            latest: latest.clone(),
+            history,
+        })),
+        None => Err(ApiError::NotFound(format!("No pit data for team {}", number)).into()),
+    }
+}
+
+/// Saves a photo to the image store and records it against a team. Uploading the same
+/// photo again just returns the existing record.
+pub async fn store_image(
+    images: &ImageStore,
+    bytes: Vec<u8>,
+    team: u32,
+    uploaded_by: Option<u32>,
+    conn: &mut SqliteConnection,
+) -> Result<StoredImage, ApiError> {
+    let size = bytes.len() as i64;
+    let store = images.clone();
+    let (id, content_type) = web::block(move || store.save(&bytes))
+        .await
+        .map_err(|e| match e {
+            BlockingError::Error(e) => ApiError::from(e),
+            BlockingError::Canceled => ApiError::Internal("Image store went away".to_string()),
+        })?;
+
+    query(
+        r#"INSERT OR IGNORE INTO team_images (id, team, content_type, size, uploaded_at, uploaded_by)
+            VALUES (?, ?, ?, ?, strftime('%s', 'now'), ?)"#,
+    )
+    .bind(&id)
+    .bind(team)
+    .bind(content_type)
+    .bind(size)
+    .bind(uploaded_by)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| ApiError::storage("Failed recording image", e))?;
+
+    let stored = query_as::<_, StoredImage>("SELECT * FROM team_images WHERE team = ? AND id = ?")
+        .bind(team)
+        .bind(&id)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| ApiError::storage("Failed reading image", e))?;
+
+    Ok(stored)
+}
+
+/// Checks every id a pit response lists names a photo already uploaded for `team`.
+pub async fn check_team_images(
+    conn: &mut SqliteConnection,
+    team: u32,
+    ids: &[String],
+) -> Result<(), ApiError> {
+    for id in ids {
+        if !ImageStore::is_valid_id(id) {
+            return Err(ImageError::InvalidId(id.clone()).into());
+        }
+
+        let uploaded: i64 =
+            query_scalar("SELECT COUNT(*) FROM team_images WHERE team = ? AND id = ?")
+                .bind(team)
+                .bind(id)
+                .fetch_one(&mut *conn)
+                .await
+                .map_err(|e| ApiError::storage("Failed reading team images", e))?;
+        if uploaded == 0 {
+            return Err(ApiError::Invalid(format!(
+                "image {} was not uploaded for team {}",
+                id, team
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Largest photo accepted by an upload.
+const MAX_IMAGE_BYTES: usize = 10 * 1024 * 1024;
+
+/// Most photos in one upload, which with [`MAX_IMAGE_BYTES`] bounds what a request can make
+/// the server hold in memory.
+const MAX_IMAGES_PER_UPLOAD: usize = 8;
+
+#[derive(Deserialize)]
+pub struct Uploader {
+    uuid: u32,
+}
+
+/// Uploads one or more photos of a team as `multipart/form-data`, one photo per part.
+/// Needs the uploading scout's token, like response ingest.
+#[actix_web::post("/teams/{number}/images")]
+pub async fn upload_team_images(
+    web::Path((number,)): web::Path<(u32,)>,
+    uploader: web::Query<Uploader>,
+    req: HttpRequest,
+    mut payload: Multipart,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    routes::scouts::authenticate(&req, uploader.uuid, &data.db).await?;
+
+    let mut uploads = Vec::new();
+    while let Some(field) = payload.next().await {
+        if uploads.len() == MAX_IMAGES_PER_UPLOAD {
+            return Err(ApiError::TooLarge(format!(
+                "Uploads are limited to {} images",
+                MAX_IMAGES_PER_UPLOAD
+            ))
+            .into());
+        }
+
+        let mut field = field?;
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field.next().await {
+            bytes.extend_from_slice(&chunk?);
+            if bytes.len() > MAX_IMAGE_BYTES {
+                return Err(ApiError::TooLarge(format!(
+                    "Images are limited to {} bytes",
+                    MAX_IMAGE_BYTES
+                ))
+                .into());
+            }
+        }
+        uploads.push(bytes);
+    }
+
+    if uploads.is_empty() {
+        return Err(ApiError::Invalid("No images in upload".to_string()).into());
+    }
+
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
+
+    let mut stored = Vec::new();
+    for bytes in uploads {
+        stored.push(store_image(&data.images, bytes, number, Some(uploader.uuid), &mut tx).await?);
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| ApiError::storage("Failed committing images", e))?;
+
+    Ok(HttpResponse::Created().json(stored))
+}
+
+#[actix_web::get("/teams/{number}/images")]
+pub async fn get_team_images(
+    web::Path((number,)): web::Path<(u32,)>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let images = query_as::<_, StoredImage>(
+        "SELECT * FROM team_images WHERE team = ? ORDER BY uploaded_at DESC, id",
+    )
+    .bind(number)
+    .fetch_all(&data.db)
+    .await
+    .map_err(|e| ApiError::storage("Failed reading team images", e))?;
+
+    Ok(HttpResponse::Ok().json(images))
+}
+
+/// The team's most recent photo. Falls back to the inline picture older pit forms stored
+/// in the database.
+#[actix_web::get("/teams/{number}/image")]
+pub async fn get_team_image(
+    web::Path((number,)): web::Path<(u32,)>,
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let latest: Option<String> = query_scalar(
+        "SELECT id FROM team_images WHERE team = ? ORDER BY uploaded_at DESC, id LIMIT 1",
+    )
+    .bind(number)
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|e| ApiError::storage("Failed reading team images", e))?;
+
+    if let Some(id) = latest {
+        let path = data.images.path(&id);
+        return routes::images::serve(&req, &id, path).await;
+    }
+
+    let img = query_scalar::<_, Option<Vec<u8>>>("SELECT img FROM images WHERE team = ?")
+        .bind(number)
+        .fetch_optional(&data.db)
+        .await
+        .map_err(|e| ApiError::storage("Failed reading team image", e))?
+        .flatten();
+
+    match img {
+        Some(raw) if !raw.is_empty() => {
+            let picture = decode_picture(&raw);
+            Ok(HttpResponse::Ok()
+                .content_type(picture.content_type)
+                .body(picture.bytes))
+        }
+        _ => Err(ApiError::NotFound(format!("No image for team {}", number)).into()),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TrendParams {
+    event: Option<String>,
+    /// Matches in each moving average. Defaults to 3.
+    window: Option<usize>,
+}
+
+/// A team's estimated score match by match, with summary statistics, for charting.
+#[actix_web::get("/teams/{number}/trend")]
+pub async fn get_team_trend(
+    web::Path((number,)): web::Path<(u32,)>,
+    params: web::Query<TrendParams>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let mut conn = data
+        .db
+        .acquire()
+        .await
+        .map_err(|e| ApiError::storage("Timed out connecting to DB", e))?;
+
+    let series = stats::load_series(&mut conn, number, params.event.as_deref(), data.game)
+        .await
+        .map_err(|e| ApiError::storage("Failed reading match responses", e))?;
+
+    if series.is_empty() {
+        return Err(ApiError::NotFound(format!("No data for team {}", number)).into());
+    }
+
+    Ok(HttpResponse::Ok().json(stats::trend(number, series, params.window.unwrap_or(3))))
+}
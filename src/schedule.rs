@@ -0,0 +1,304 @@
+//! Qualification schedules and the scouting assignments generated from them.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use sqlx::{query, query_as, SqliteConnection};
+
+use crate::game::Alliance;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledMatch {
+    pub match_number: i16,
+    /// Teams in driver station order.
+    pub red: Vec<u32>,
+    pub blue: Vec<u32>,
+}
+
+impl ScheduledMatch {
+    /// Every robot in the match as (alliance, station, team), stations counted from 1.
+    pub fn stations(&self) -> impl Iterator<Item = (Alliance, u8, u32)> + '_ {
+        let red = self
+            .red
+            .iter()
+            .enumerate()
+            .map(|(i, &team)| (Alliance::Red, i as u8 + 1, team));
+        let blue = self
+            .blue
+            .iter()
+            .enumerate()
+            .map(|(i, &team)| (Alliance::Blue, i as u8 + 1, team));
+        red.chain(blue)
+    }
+}
+
+/// One row of a schedule CSV, e.g. as exported from The Blue Alliance or the FMS.
+#[derive(Deserialize)]
+struct CsvRow {
+    match_number: i16,
+    red1: u32,
+    red2: u32,
+    red3: u32,
+    blue1: u32,
+    blue2: u32,
+    blue3: u32,
+}
+
+/// Parses a schedule CSV with a `match_number,red1,red2,red3,blue1,blue2,blue3` header.
+pub fn parse_csv(body: &[u8]) -> Result<Vec<ScheduledMatch>, String> {
+    csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(body)
+        .deserialize::<CsvRow>()
+        .map(|row| {
+            row.map(|row| ScheduledMatch {
+                match_number: row.match_number,
+                red: vec![row.red1, row.red2, row.red3],
+                blue: vec![row.blue1, row.blue2, row.blue3],
+            })
+            .map_err(|e| e.to_string())
+        })
+        .collect()
+}
+
+/// Rejects schedules that can't have been played: repeated match numbers, empty or
+/// oversized alliances, or a team on the field twice in one match.
+pub fn validate(schedule: &[ScheduledMatch]) -> Result<(), String> {
+    let mut numbers = HashSet::new();
+
+    for scheduled in schedule {
+        if !numbers.insert(scheduled.match_number) {
+            return Err(format!("match {} is listed twice", scheduled.match_number));
+        }
+
+        for (name, alliance) in [("red", &scheduled.red), ("blue", &scheduled.blue)] {
+            if alliance.is_empty() || alliance.len() > 3 {
+                return Err(format!(
+                    "match {} has {} {} teams, expected 1 to 3",
+                    scheduled.match_number,
+                    alliance.len(),
+                    name
+                ));
+            }
+        }
+
+        let mut teams = HashSet::new();
+        for (_, _, team) in scheduled.stations() {
+            if !teams.insert(team) {
+                return Err(format!(
+                    "team {} appears twice in match {}",
+                    team, scheduled.match_number
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Assignment {
+    pub match_number: i16,
+    pub uuid: u32,
+    pub alliance: Alliance,
+    pub station: u8,
+    pub team_number: u32,
+}
+
+/// Spreads `scouts` over the robots in `schedule`.
+///
+/// Scouts are handed out round robin, so with more than six of them the extras rotate
+/// through breaks. The station each scout watches also shifts every match, so nobody spends
+/// the whole event staring at the same driver station.
+pub fn assign(schedule: &[ScheduledMatch], scouts: &[u32]) -> Vec<Assignment> {
+    let mut assignments = Vec::new();
+    if scouts.is_empty() {
+        return assignments;
+    }
+
+    let mut next_scout = 0;
+    for (i, scheduled) in schedule.iter().enumerate() {
+        let stations: Vec<_> = scheduled.stations().collect();
+        let covered = stations.len().min(scouts.len());
+
+        for j in 0..covered {
+            let (alliance, station, team_number) = stations[(j + i) % stations.len()];
+            assignments.push(Assignment {
+                match_number: scheduled.match_number,
+                uuid: scouts[(next_scout + j) % scouts.len()],
+                alliance,
+                station,
+                team_number,
+            });
+        }
+
+        next_scout = (next_scout + covered) % scouts.len();
+    }
+
+    assignments
+}
+
+#[derive(sqlx::FromRow)]
+struct StationRow {
+    match_number: i16,
+    alliance: Alliance,
+    team_number: u32,
+}
+
+/// Reads an event's stored schedule, in match order.
+pub async fn load(
+    conn: &mut SqliteConnection,
+    event: &str,
+) -> Result<Vec<ScheduledMatch>, sqlx::Error> {
+    let rows = query_as::<_, StationRow>(
+        r#"SELECT m.match_number, t.alliance, t.team_number
+            FROM matches m JOIN match_teams t
+            ON t.event = m.event AND t.match_number = m.match_number
+            WHERE m.event = ? ORDER BY m.match_number, t.alliance DESC, t.station"#,
+    )
+    .bind(event)
+    .fetch_all(conn)
+    .await?;
+
+    let mut schedule: Vec<ScheduledMatch> = Vec::new();
+    for row in rows {
+        if schedule.last().map(|m| m.match_number) != Some(row.match_number) {
+            schedule.push(ScheduledMatch {
+                match_number: row.match_number,
+                red: Vec::new(),
+                blue: Vec::new(),
+            });
+        }
+
+        let scheduled = schedule.last_mut().unwrap();
+        match row.alliance {
+            Alliance::Red => scheduled.red.push(row.team_number),
+            Alliance::Blue => scheduled.blue.push(row.team_number),
+        }
+    }
+
+    Ok(schedule)
+}
+
+/// Replaces an event's schedule. Any scouting assignments for it are cleared, since they
+/// were generated from the old one.
+pub async fn store(
+    conn: &mut SqliteConnection,
+    event: &str,
+    schedule: &[ScheduledMatch],
+) -> Result<(), sqlx::Error> {
+    for table in ["matches", "match_teams", "assignments"] {
+        query(&format!("DELETE FROM {} WHERE event = ?", table))
+            .bind(event)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    for scheduled in schedule {
+        query(r#"INSERT INTO matches ("event", "match_number", "group") VALUES (?, ?, 'qm')"#)
+            .bind(event)
+            .bind(scheduled.match_number)
+            .execute(&mut *conn)
+            .await?;
+
+        for (alliance, station, team_number) in scheduled.stations() {
+            query("INSERT INTO match_teams VALUES (?, ?, ?, ?, ?)")
+                .bind(event)
+                .bind(scheduled.match_number)
+                .bind(alliance)
+                .bind(station)
+                .bind(team_number)
+                .execute(&mut *conn)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(matches: i16) -> Vec<ScheduledMatch> {
+        (1..=matches)
+            .map(|n| {
+                let base = n as u32 * 10;
+                ScheduledMatch {
+                    match_number: n,
+                    red: vec![base + 1, base + 2, base + 3],
+                    blue: vec![base + 4, base + 5, base + 6],
+                }
+            })
+            .collect()
+    }
+
+    fn scouts_in(assignments: &[Assignment], match_number: i16) -> Vec<u32> {
+        assignments
+            .iter()
+            .filter(|a| a.match_number == match_number)
+            .map(|a| a.uuid)
+            .collect()
+    }
+
+    #[test]
+    fn extra_scouts_rotate_through_breaks() {
+        let assignments = assign(&schedule(3), &[1, 2, 3, 4, 5, 6, 7, 8]);
+
+        assert_eq!(assignments.len(), 18);
+        assert_eq!(scouts_in(&assignments, 1), [1, 2, 3, 4, 5, 6]);
+        assert_eq!(scouts_in(&assignments, 2), [7, 8, 1, 2, 3, 4]);
+        assert_eq!(scouts_in(&assignments, 3), [5, 6, 7, 8, 1, 2]);
+    }
+
+    #[test]
+    fn stations_shift_every_match() {
+        let assignments = assign(&schedule(6), &[1, 2, 3, 4, 5, 6]);
+
+        let stations_of = |uuid| {
+            assignments
+                .iter()
+                .filter(|a| a.uuid == uuid)
+                .map(|a| (a.alliance, a.station))
+                .collect::<Vec<_>>()
+        };
+        for uuid in 1..=6 {
+            let mut stations = stations_of(uuid);
+            assert_eq!(stations.len(), 6);
+            stations
+                .sort_unstable_by_key(|&(alliance, station)| (alliance == Alliance::Blue, station));
+            stations.dedup();
+            assert_eq!(stations.len(), 6, "scout {} repeated a station", uuid);
+        }
+
+        // the team recorded is the one at the assigned station
+        for a in &assignments {
+            let offset = if a.alliance == Alliance::Red { 0 } else { 3 };
+            assert_eq!(
+                a.team_number,
+                a.match_number as u32 * 10 + offset + a.station as u32
+            );
+        }
+    }
+
+    #[test]
+    fn short_staffed_covers_what_it_can() {
+        let assignments = assign(&schedule(2), &[1, 2, 3, 4]);
+
+        assert_eq!(scouts_in(&assignments, 1), [1, 2, 3, 4]);
+        assert_eq!(scouts_in(&assignments, 2), [1, 2, 3, 4]);
+        assert!(assign(&schedule(2), &[]).is_empty());
+    }
+
+    #[test]
+    fn validate_rejects_a_team_twice_in_one_match() {
+        let mut matches = schedule(1);
+        matches[0].blue[0] = matches[0].red[0];
+
+        assert!(validate(&schedule(2)).is_ok());
+        assert_eq!(
+            validate(&matches).unwrap_err(),
+            "team 11 appears twice in match 1"
+        );
+    }
+}
@@ -0,0 +1,31 @@
+//! Scout registration and per-scout tokens.
+//!
+//! Tokens are handed to the scout once, when issued. Only their SHA-256 hash is stored.
+
+use rand::RngCore;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// A random 256-bit token, hex encoded.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// How much a scout has actually submitted.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ScoutStats {
+    pub uuid: u32,
+    pub name: Option<String>,
+    pub team_number: Option<u32>,
+    pub match_responses: i64,
+    pub pit_responses: i64,
+    /// Distinct teams covered by either kind of response.
+    pub teams_covered: i64,
+    pub last_submission: Option<i64>,
+}
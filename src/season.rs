@@ -0,0 +1,190 @@
+//! Per-season game rules.
+//!
+//! What each action is worth, which field combinations are valid, how responses roll up
+//! into `team_details` and which bonus ranking points are on offer live behind the
+//! [`Game`] trait, and the server scores whichever season its config names.
+//!
+//! The form itself is not per season yet. `FormIngest::Match` and the `match_responses` and
+//! `team_details` columns are still the 2022 layout, which [`Game::match_fields`] only
+//! describes, so a season with different fields needs those changed as well.
+
+use std::cmp::Ordering;
+
+use serde::Serialize;
+
+mod rapid_react;
+
+pub use rapid_react::RapidReact;
+
+/// Every season this server knows how to score, newest first.
+pub static SEASONS: &[&dyn Game] = &[&RapidReact];
+
+/// Looks up a season by its year.
+pub fn by_year(year: u16) -> Option<&'static dyn Game> {
+    SEASONS.iter().copied().find(|game| game.year() == year)
+}
+
+/// The newest season, used when the config doesn't name one.
+pub fn current() -> &'static dyn Game {
+    SEASONS[0]
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum FieldKind {
+    Bool,
+    Count,
+    /// An enumerated level, such as a climb rung, inclusive on both ends.
+    Level {
+        min: i8,
+        max: i8,
+    },
+    Score,
+    Text,
+}
+
+/// A single field on the match scouting form.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Field {
+    pub name: &'static str,
+    pub kind: FieldKind,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
+pub enum Points {
+    /// Points per unit of the field, e.g. per ball scored. Bools count as 0 or 1.
+    PerUnit(i64),
+    /// Points for reaching a specific level. Values not listed are worth nothing.
+    Levels(&'static [(i64, i64)]),
+}
+
+/// What a field on the match form is worth towards a robot's estimated score.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct PointValue {
+    pub field: &'static str,
+    pub points: Points,
+}
+
+impl PointValue {
+    pub fn score(&self, value: i64) -> i64 {
+        match self.points {
+            Points::PerUnit(points) => value * points,
+            Points::Levels(levels) => levels
+                .iter()
+                .find(|(level, _)| *level == value)
+                .map(|(_, points)| *points)
+                .unwrap_or(0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AggregateOp {
+    Sum,
+    Max,
+    /// True if the field was ever non-zero.
+    Any,
+}
+
+/// How a `team_details` column is built from one field of every match response.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Aggregate {
+    pub column: &'static str,
+    pub field: &'static str,
+    pub op: AggregateOp,
+}
+
+/// A bonus ranking point, and how close an alliance came to it.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct BonusRp {
+    pub name: &'static str,
+    pub value: i64,
+    pub threshold: i64,
+    pub earned: bool,
+}
+
+/// Fields whose total can never be more than another field, like cargo scored and cargo shot.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SumLimit {
+    pub fields: &'static [&'static str],
+    pub at_most: &'static str,
+}
+
+/// Anything that can report the numeric value of a form field by name.
+pub trait FieldValues {
+    fn value(&self, field: &str) -> Option<i64>;
+}
+
+impl FieldValues for &[(&str, i64)] {
+    fn value(&self, field: &str) -> Option<i64> {
+        self.iter()
+            .find(|(name, _)| *name == field)
+            .map(|(_, v)| *v)
+    }
+}
+
+pub trait Game: Send + Sync {
+    fn year(&self) -> u16;
+
+    fn name(&self) -> &'static str;
+
+    /// Fields a match response for this season is expected to carry.
+    fn match_fields(&self) -> &'static [Field];
+
+    fn point_values(&self) -> &'static [PointValue];
+
+    fn aggregates(&self) -> &'static [Aggregate];
+
+    /// Relationships between match fields that a valid response has to satisfy.
+    fn sum_limits(&self) -> &'static [SumLimit] {
+        &[]
+    }
+
+    /// Estimates one robot's contribution to its alliance score from its match response.
+    fn score(&self, values: &dyn FieldValues) -> i64 {
+        self.point_values()
+            .iter()
+            .map(|pv| pv.score(values.value(pv.field).unwrap_or(0)))
+            .sum()
+    }
+
+    /// Ranking points an alliance earns from the result alone.
+    fn result_rp(&self, score: i64, opponent_score: i64) -> u8 {
+        match score.cmp(&opponent_score) {
+            Ordering::Greater => 2,
+            Ordering::Equal => 1,
+            Ordering::Less => 0,
+        }
+    }
+
+    /// Bonus ranking points on offer, judged from the match responses of an alliance's robots.
+    fn bonus_rp(&self, _robots: &[&dyn FieldValues]) -> Vec<BonusRp> {
+        Vec::new()
+    }
+}
+
+/// A serializable snapshot of a season's rules, for clients building their forms.
+#[derive(Serialize)]
+pub struct Rules {
+    pub year: u16,
+    pub name: &'static str,
+    pub match_fields: &'static [Field],
+    pub point_values: &'static [PointValue],
+    pub aggregates: &'static [Aggregate],
+    pub sum_limits: &'static [SumLimit],
+}
+
+impl From<&dyn Game> for Rules {
+    fn from(game: &dyn Game) -> Self {
+        Rules {
+            year: game.year(),
+            name: game.name(),
+            match_fields: game.match_fields(),
+            point_values: game.point_values(),
+            aggregates: game.aggregates(),
+            sum_limits: game.sum_limits(),
+        }
+    }
+}
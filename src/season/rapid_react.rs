@@ -0,0 +1,315 @@
+use super::{
+    Aggregate, AggregateOp, BonusRp, Field, FieldKind, FieldValues, Game, PointValue, Points,
+    SumLimit,
+};
+
+/// The 2022 game, Rapid React.
+pub struct RapidReact;
+
+const MATCH_FIELDS: &[Field] = &[
+    Field {
+        name: "did_preload",
+        kind: FieldKind::Bool,
+    },
+    Field {
+        name: "did_taxi",
+        kind: FieldKind::Bool,
+    },
+    Field {
+        name: "got_field_cargo",
+        kind: FieldKind::Bool,
+    },
+    Field {
+        name: "did_hp_shot",
+        kind: FieldKind::Bool,
+    },
+    Field {
+        name: "did_hp_sink",
+        kind: FieldKind::Bool,
+    },
+    Field {
+        name: "auto_scored_lower",
+        kind: FieldKind::Count,
+    },
+    Field {
+        name: "auto_scored_upper",
+        kind: FieldKind::Count,
+    },
+    Field {
+        name: "auto_shots",
+        kind: FieldKind::Count,
+    },
+    Field {
+        name: "teleop_scored_lower",
+        kind: FieldKind::Count,
+    },
+    Field {
+        name: "teleop_scored_upper",
+        kind: FieldKind::Count,
+    },
+    Field {
+        name: "teleop_shots",
+        kind: FieldKind::Count,
+    },
+    Field {
+        name: "pins",
+        kind: FieldKind::Count,
+    },
+    Field {
+        name: "times_pinned",
+        kind: FieldKind::Count,
+    },
+    Field {
+        name: "penalties",
+        kind: FieldKind::Count,
+    },
+    Field {
+        name: "climb",
+        kind: FieldKind::Level { min: -1, max: 3 },
+    },
+    Field {
+        name: "performance",
+        kind: FieldKind::Count,
+    },
+    Field {
+        name: "comments",
+        kind: FieldKind::Text,
+    },
+    Field {
+        name: "red_score",
+        kind: FieldKind::Score,
+    },
+    Field {
+        name: "blue_score",
+        kind: FieldKind::Score,
+    },
+];
+
+// every shot counts towards the shots field, whether it scored or not
+const SUM_LIMITS: &[SumLimit] = &[
+    SumLimit {
+        fields: &["auto_scored_lower", "auto_scored_upper"],
+        at_most: "auto_shots",
+    },
+    SumLimit {
+        fields: &["teleop_scored_lower", "teleop_scored_upper"],
+        at_most: "teleop_shots",
+    },
+];
+
+// climb is -1 for no attempt, then 0..=3 for the low, mid, high and traversal rungs
+const CLIMB_POINTS: &[(i64, i64)] = &[(-1, 0), (0, 4), (1, 6), (2, 10), (3, 15)];
+
+const POINT_VALUES: &[PointValue] = &[
+    PointValue {
+        field: "did_taxi",
+        points: Points::PerUnit(2),
+    },
+    PointValue {
+        field: "auto_scored_upper",
+        points: Points::PerUnit(4),
+    },
+    PointValue {
+        field: "auto_scored_lower",
+        points: Points::PerUnit(2),
+    },
+    PointValue {
+        field: "teleop_scored_upper",
+        points: Points::PerUnit(2),
+    },
+    PointValue {
+        field: "teleop_scored_lower",
+        points: Points::PerUnit(1),
+    },
+    PointValue {
+        field: "climb",
+        points: Points::Levels(CLIMB_POINTS),
+    },
+];
+
+const CARGO_FIELDS: &[&str] = &[
+    "auto_scored_lower",
+    "auto_scored_upper",
+    "teleop_scored_lower",
+    "teleop_scored_upper",
+];
+
+// the cargo bonus needs 20 cargo, or 18 if the alliance scored a quintet of 5 in auto
+const CARGO_BONUS: i64 = 20;
+const CARGO_BONUS_QUINTET: i64 = 18;
+const QUINTET: i64 = 5;
+const HANGAR_BONUS: i64 = 16;
+
+const AGGREGATES: &[Aggregate] = &[
+    Aggregate {
+        column: "taxi_true",
+        field: "did_taxi",
+        op: AggregateOp::Any,
+    },
+    Aggregate {
+        column: "preload",
+        field: "did_preload",
+        op: AggregateOp::Any,
+    },
+    Aggregate {
+        column: "auto_shoot_true",
+        field: "auto_shots",
+        op: AggregateOp::Any,
+    },
+    Aggregate {
+        column: "auto_upper_accum",
+        field: "auto_scored_upper",
+        op: AggregateOp::Sum,
+    },
+    Aggregate {
+        column: "auto_lower_accum",
+        field: "auto_scored_lower",
+        op: AggregateOp::Sum,
+    },
+    Aggregate {
+        column: "shots_accum",
+        field: "teleop_shots",
+        op: AggregateOp::Sum,
+    },
+    Aggregate {
+        column: "shots_upper_accum",
+        field: "teleop_scored_upper",
+        op: AggregateOp::Sum,
+    },
+    Aggregate {
+        column: "shots_lower_accum",
+        field: "teleop_scored_lower",
+        op: AggregateOp::Sum,
+    },
+    Aggregate {
+        column: "climb",
+        field: "climb",
+        op: AggregateOp::Max,
+    },
+];
+
+impl Game for RapidReact {
+    fn year(&self) -> u16 {
+        2022
+    }
+
+    fn name(&self) -> &'static str {
+        "Rapid React"
+    }
+
+    fn match_fields(&self) -> &'static [Field] {
+        MATCH_FIELDS
+    }
+
+    fn point_values(&self) -> &'static [PointValue] {
+        POINT_VALUES
+    }
+
+    fn aggregates(&self) -> &'static [Aggregate] {
+        AGGREGATES
+    }
+
+    fn sum_limits(&self) -> &'static [SumLimit] {
+        SUM_LIMITS
+    }
+
+    fn bonus_rp(&self, robots: &[&dyn FieldValues]) -> Vec<BonusRp> {
+        let total = |fields: &[&str]| -> i64 {
+            robots
+                .iter()
+                .flat_map(|r| fields.iter().map(move |f| r.value(f).unwrap_or(0)))
+                .sum()
+        };
+
+        let cargo = total(CARGO_FIELDS);
+        let auto_cargo = total(&CARGO_FIELDS[..2]);
+        let cargo_threshold = if auto_cargo >= QUINTET {
+            CARGO_BONUS_QUINTET
+        } else {
+            CARGO_BONUS
+        };
+
+        let climb = POINT_VALUES.iter().find(|pv| pv.field == "climb").unwrap();
+        let hangar: i64 = robots
+            .iter()
+            .map(|r| climb.score(r.value("climb").unwrap_or(-1)))
+            .sum();
+
+        vec![
+            BonusRp {
+                name: "cargo",
+                value: cargo,
+                threshold: cargo_threshold,
+                earned: cargo >= cargo_threshold,
+            },
+            BonusRp {
+                name: "hangar",
+                value: hangar,
+                threshold: HANGAR_BONUS,
+                earned: hangar >= HANGAR_BONUS,
+            },
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bonus RP for an alliance of robots, each given as `(auto cargo, teleop cargo, climb)`.
+    fn bonuses(robots: &[(i64, i64, i64)]) -> Vec<BonusRp> {
+        let values: Vec<[(&str, i64); 3]> = robots
+            .iter()
+            .map(|&(auto, teleop, climb)| {
+                [
+                    ("auto_scored_upper", auto),
+                    ("teleop_scored_upper", teleop),
+                    ("climb", climb),
+                ]
+            })
+            .collect();
+        let slices: Vec<&[(&str, i64)]> = values.iter().map(|v| &v[..]).collect();
+        let robots: Vec<&dyn FieldValues> = slices.iter().map(|s| s as &dyn FieldValues).collect();
+        RapidReact.bonus_rp(&robots)
+    }
+
+    fn cargo(bonuses: &[BonusRp]) -> &BonusRp {
+        bonuses.iter().find(|b| b.name == "cargo").unwrap()
+    }
+
+    #[test]
+    fn cargo_bonus_needs_20_without_quintet() {
+        let short = bonuses(&[(1, 6, -1), (1, 6, -1), (2, 2, -1)]);
+        assert_eq!((cargo(&short).value, cargo(&short).threshold), (18, 20));
+        assert!(!cargo(&short).earned);
+
+        let enough = bonuses(&[(1, 7, -1), (1, 7, -1), (2, 2, -1)]);
+        assert_eq!(cargo(&enough).value, 20);
+        assert!(cargo(&enough).earned);
+    }
+
+    #[test]
+    fn cargo_bonus_needs_18_with_quintet() {
+        let quintet = bonuses(&[(2, 5, -1), (2, 5, -1), (1, 3, -1)]);
+        assert_eq!((cargo(&quintet).value, cargo(&quintet).threshold), (18, 18));
+        assert!(cargo(&quintet).earned);
+
+        let short = bonuses(&[(2, 5, -1), (2, 5, -1), (1, 2, -1)]);
+        assert_eq!(cargo(&short).value, 17);
+        assert!(!cargo(&short).earned);
+    }
+
+    #[test]
+    fn hangar_bonus_counts_climb_points() {
+        let hangar = |robots| {
+            bonuses(robots)
+                .into_iter()
+                .find(|b| b.name == "hangar")
+                .unwrap()
+        };
+
+        // high and mid rungs make 16 exactly
+        assert!(hangar(&[(0, 0, 2), (0, 0, 1), (0, 0, -1)]).earned);
+        assert!(!hangar(&[(0, 0, 2), (0, 0, 0), (0, 0, -1)]).earned);
+    }
+}
@@ -0,0 +1,244 @@
+//! Per-team score trends, for telling a steadily improving robot from one good match.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use sqlx::{query_as, SqliteConnection};
+
+use crate::{consensus, db::MatchResponse, game::FormIngest, season::Game};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TrendPoint {
+    pub event: String,
+    pub match_number: i16,
+    pub score: i64,
+    /// Mean of this match and the ones just before it, up to the window size.
+    pub moving_average: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Trend {
+    pub team: u32,
+    pub matches: usize,
+    pub window: usize,
+    pub mean: f64,
+    /// Sample standard deviation; 0 with fewer than two matches.
+    pub std_dev: f64,
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+    /// Least squares change in score per match played. Positive means improving.
+    pub slope: f64,
+    /// Oldest match first.
+    pub series: Vec<TrendPoint>,
+}
+
+/// A team's estimated score in every observed match, oldest first. Double-scouted matches
+/// are merged. Events are ordered by when they were first scouted, matches by number.
+pub async fn load_series(
+    conn: &mut SqliteConnection,
+    team: u32,
+    event: Option<&str>,
+    game: &dyn Game,
+) -> Result<Vec<(String, i16, i64)>, sqlx::Error> {
+    let responses = query_as::<_, MatchResponse>(
+        "SELECT * FROM match_responses WHERE team_number = ?1 AND (?2 IS NULL OR event = ?2)",
+    )
+    .bind(team)
+    .bind(event)
+    .fetch_all(conn)
+    .await?;
+
+    let mut started: HashMap<String, i32> = HashMap::new();
+    for r in &responses {
+        let start = started.entry(r.event.clone()).or_insert(r.timestamp);
+        *start = (*start).min(r.timestamp);
+    }
+
+    let mut series: Vec<(String, i16, i64)> = consensus::canonical(responses)
+        .into_iter()
+        .map(|r| {
+            let (event, match_number) = (r.event.clone(), r.match_number);
+            (event, match_number, game.score(&FormIngest::from(r)))
+        })
+        .collect();
+
+    series.sort_by_key(|(event, match_number, _)| (started[event], event.clone(), *match_number));
+
+    Ok(series)
+}
+
+pub fn trend(team: u32, series: Vec<(String, i16, i64)>, window: usize) -> Trend {
+    let window = window.max(1);
+    let scores: Vec<f64> = series.iter().map(|(_, _, s)| *s as f64).collect();
+    let n = scores.len();
+
+    let mean = if n == 0 {
+        0.0
+    } else {
+        scores.iter().sum::<f64>() / n as f64
+    };
+
+    let std_dev = if n < 2 {
+        0.0
+    } else {
+        (scores.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (n - 1) as f64).sqrt()
+    };
+
+    // least squares fit of score against match index
+    let x_mean = (n as f64 - 1.0) / 2.0;
+    let (mut covariance, mut variance) = (0.0, 0.0);
+    for (i, s) in scores.iter().enumerate() {
+        covariance += (i as f64 - x_mean) * (s - mean);
+        variance += (i as f64 - x_mean).powi(2);
+    }
+    let slope = if variance == 0.0 {
+        0.0
+    } else {
+        covariance / variance
+    };
+
+    let points = series
+        .into_iter()
+        .enumerate()
+        .map(|(i, (event, match_number, score))| {
+            let recent = &scores[(i + 1).saturating_sub(window)..=i];
+            TrendPoint {
+                event,
+                match_number,
+                score,
+                moving_average: recent.iter().sum::<f64>() / recent.len() as f64,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    Trend {
+        team,
+        matches: n,
+        window,
+        mean,
+        std_dev,
+        min: points.iter().map(|p| p.score).min(),
+        max: points.iter().map(|p| p.score).max(),
+        slope,
+        series: points,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        season,
+        testing::{insert_match, match_response, with_database},
+    };
+
+    fn series(scores: &[i64]) -> Vec<(String, i16, i64)> {
+        scores
+            .iter()
+            .enumerate()
+            .map(|(i, &score)| ("2022test".to_string(), i as i16 + 1, score))
+            .collect()
+    }
+
+    fn averages(trend: &Trend) -> Vec<f64> {
+        trend.series.iter().map(|p| p.moving_average).collect()
+    }
+
+    #[test]
+    fn improving_team_has_a_positive_slope() {
+        let trend = trend(254, series(&[10, 20, 30, 40]), 2);
+
+        assert_eq!(trend.matches, 4);
+        assert_eq!(trend.mean, 25.0);
+        assert!((trend.std_dev - 12.909944).abs() < 1e-6);
+        assert_eq!((trend.min, trend.max), (Some(10), Some(40)));
+        assert_eq!(trend.slope, 10.0);
+        assert_eq!(averages(&trend), vec![10.0, 15.0, 25.0, 35.0]);
+    }
+
+    #[test]
+    fn window_larger_than_the_data_averages_everything_so_far() {
+        let trend = trend(254, series(&[30, 10, 20]), 10);
+
+        assert_eq!(trend.window, 10);
+        assert_eq!(averages(&trend), vec![30.0, 20.0, 20.0]);
+        assert_eq!(trend.slope, -5.0);
+    }
+
+    #[test]
+    fn zero_window_averages_single_matches() {
+        let trend = trend(254, series(&[30, 10]), 0);
+
+        assert_eq!(trend.window, 1);
+        assert_eq!(averages(&trend), vec![30.0, 10.0]);
+    }
+
+    #[test]
+    fn single_match_has_no_spread_or_slope() {
+        let trend = trend(254, series(&[42]), 3);
+
+        assert_eq!(trend.mean, 42.0);
+        assert_eq!(trend.std_dev, 0.0);
+        assert_eq!(trend.slope, 0.0);
+        assert_eq!((trend.min, trend.max), (Some(42), Some(42)));
+        assert_eq!(averages(&trend), vec![42.0]);
+    }
+
+    #[test]
+    fn steady_scores_have_no_spread_or_slope() {
+        let trend = trend(254, series(&[25, 25, 25, 25]), 3);
+
+        assert_eq!(trend.std_dev, 0.0);
+        assert_eq!(trend.slope, 0.0);
+        assert_eq!(averages(&trend), vec![25.0; 4]);
+    }
+
+    #[test]
+    fn no_matches_gives_an_empty_trend() {
+        let trend = trend(254, Vec::new(), 3);
+
+        assert_eq!(trend.matches, 0);
+        assert_eq!((trend.mean, trend.std_dev, trend.slope), (0.0, 0.0, 0.0));
+        assert_eq!((trend.min, trend.max), (None, None));
+        assert!(trend.series.is_empty());
+    }
+
+    #[test]
+    fn series_orders_events_by_when_they_started() {
+        with_database(|mut conn| async move {
+            let mut later = match_response(1, 254);
+            later.event = "2022b".to_string();
+            later.timestamp = 100;
+            let mut earlier = match_response(5, 254);
+            earlier.event = "2022a".to_string();
+            earlier.timestamp = 50;
+            let mut earlier_first = match_response(2, 254);
+            earlier_first.event = "2022a".to_string();
+            earlier_first.timestamp = 60;
+            for r in [&later, &earlier, &earlier_first, &match_response(1, 1114)] {
+                insert_match(&mut conn, r).await;
+            }
+
+            let game = season::current();
+            let order: Vec<(String, i16)> = load_series(&mut conn, 254, None, game)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|(event, match_number, _)| (event, match_number))
+                .collect();
+            assert_eq!(
+                order,
+                vec![
+                    ("2022a".to_string(), 2),
+                    ("2022a".to_string(), 5),
+                    ("2022b".to_string(), 1),
+                ]
+            );
+
+            let only_b = load_series(&mut conn, 254, Some("2022b"), game)
+                .await
+                .unwrap();
+            assert_eq!(only_b.len(), 1);
+        })
+    }
+}
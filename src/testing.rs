@@ -0,0 +1,94 @@
+//! Fixtures shared by the unit tests of modules that need a database.
+
+use futures::executor::block_on;
+use sqlx::{query, Connection, SqliteConnection};
+
+use crate::{db::MatchResponse, migrations};
+
+/// Runs a test body that needs a database against a fresh, fully migrated one in memory.
+pub fn with_database<T>(test: impl FnOnce(SqliteConnection) -> T) -> T::Output
+where
+    T: std::future::Future,
+{
+    block_on(async {
+        let mut conn = SqliteConnection::connect("sqlite::memory:").await.unwrap();
+        migrations::migrate(&mut conn).await.unwrap();
+        test(conn).await
+    })
+}
+
+/// A match response with nothing scored, for tests to fill in.
+pub fn match_response(match_number: i16, team_number: u32) -> MatchResponse {
+    MatchResponse {
+        id: 0,
+        timestamp: match_number as i32,
+        uuid: 1,
+        event: "2022test".to_string(),
+        team_number,
+        match_number,
+        did_preload: false,
+        did_taxi: false,
+        got_field_cargo: false,
+        did_hp_shot: false,
+        did_hp_sink: false,
+        auto_scored_lower: 0,
+        auto_scored_upper: 0,
+        auto_shots: 0,
+        teleop_scored_lower: 0,
+        teleop_scored_upper: 0,
+        teleop_shots: 0,
+        pins: 0,
+        times_pinned: 0,
+        penalties: 0,
+        performance: 0,
+        red_score: 0,
+        blue_score: 0,
+        climb: -1,
+        comment: String::new(),
+        alliance: None,
+        submission_id: None,
+    }
+}
+
+/// Stores a match response as ingest would, returning its new id.
+pub async fn insert_match(conn: &mut SqliteConnection, r: &MatchResponse) -> i64 {
+    query(
+        r#"INSERT INTO match_responses (
+            timestamp, uuid, event, team_number, match_number, did_preload, did_taxi,
+            got_field_cargo, did_hp_shot, did_hp_sink, auto_scored_lower, auto_scored_upper,
+            auto_shots, teleop_scored_lower, teleop_scored_upper, teleop_shots, pins,
+            times_pinned, penalties, performance, red_score, blue_score, climb, comment,
+            alliance, submission_id
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
+    )
+    .bind(r.timestamp)
+    .bind(r.uuid)
+    .bind(&r.event)
+    .bind(r.team_number)
+    .bind(r.match_number)
+    .bind(r.did_preload)
+    .bind(r.did_taxi)
+    .bind(r.got_field_cargo)
+    .bind(r.did_hp_shot)
+    .bind(r.did_hp_sink)
+    .bind(r.auto_scored_lower)
+    .bind(r.auto_scored_upper)
+    .bind(r.auto_shots)
+    .bind(r.teleop_scored_lower)
+    .bind(r.teleop_scored_upper)
+    .bind(r.teleop_shots)
+    .bind(r.pins)
+    .bind(r.times_pinned)
+    .bind(r.penalties)
+    .bind(r.performance)
+    .bind(r.red_score)
+    .bind(r.blue_score)
+    .bind(r.climb)
+    .bind(&r.comment)
+    .bind(r.alliance)
+    .bind(&r.submission_id)
+    .execute(conn)
+    .await
+    .unwrap()
+    .last_insert_rowid()
+}
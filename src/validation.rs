@@ -0,0 +1,561 @@
+//! Checks submitted forms before they are stored.
+//!
+//! Values that can't be right, like a negative count or more cargo scored than shot, are
+//! errors and the form is rejected. Values that are possible but unlikely, like a six ball
+//! auto, are warnings: the form is stored and the scout is asked to double check.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use sqlx::{query_scalar, SqliteConnection};
+
+use crate::{
+    game::FormIngest,
+    season::{FieldKind, FieldValues, Game},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// One problem with one field of a form.
+#[derive(Debug, Clone, Serialize)]
+pub struct Issue {
+    pub field: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Issue {
+    fn error(field: &str, message: String) -> Self {
+        Issue {
+            field: field.to_string(),
+            severity: Severity::Error,
+            message,
+        }
+    }
+
+    fn warning(field: &str, message: String) -> Self {
+        Issue {
+            field: field.to_string(),
+            severity: Severity::Warning,
+            message,
+        }
+    }
+}
+
+/// The adjustable part of validation, read from the `[validation]` table of the config file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rules {
+    pub max_team_number: u32,
+    /// How to treat a match response for an event the server doesn't know. Only applies once
+    /// it knows at least one, from the active event, `events` or an uploaded schedule.
+    pub unknown_event: Severity,
+    /// Events to accept besides the active event and those with a schedule.
+    pub events: Vec<String>,
+    /// Highest believable value of a field, on either form. Anything above is accepted with
+    /// a warning. Setting this in the config file replaces the defaults rather than adding
+    /// to them.
+    pub warn_above: BTreeMap<String, i64>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        let warn_above = [
+            ("auto_scored_lower", 5),
+            ("auto_scored_upper", 5),
+            ("teleop_scored_lower", 30),
+            ("teleop_scored_upper", 30),
+            ("penalties", 5),
+            ("performance", 10),
+            ("red_score", 200),
+            ("blue_score", 200),
+            ("weight", 125),
+            ("build_quality", 10),
+            ("driver_team", 10),
+            ("confidence", 10),
+        ];
+
+        Rules {
+            max_team_number: 9999,
+            unknown_event: Severity::Error,
+            events: Vec::new(),
+            warn_above: warn_above
+                .iter()
+                .map(|&(field, max)| (field.to_string(), max))
+                .collect(),
+        }
+    }
+}
+
+/// Events a match response may be for: the active one, any listed in the rules, and any
+/// with a schedule.
+pub async fn known_events(
+    conn: &mut SqliteConnection,
+    active: Option<&str>,
+    rules: &Rules,
+) -> Result<Vec<String>, sqlx::Error> {
+    let mut events: Vec<String> = query_scalar("SELECT DISTINCT event FROM matches")
+        .fetch_all(conn)
+        .await?;
+
+    events.extend(active.map(str::to_string));
+    events.extend(rules.events.iter().cloned());
+
+    Ok(events)
+}
+
+/// Every issue with a form, errors and warnings together. An empty `known_events` means any
+/// event is accepted.
+pub fn check(
+    form: &FormIngest,
+    game: &dyn Game,
+    rules: &Rules,
+    known_events: &[String],
+) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    match form {
+        FormIngest::Match {
+            event,
+            match_number,
+            team_number,
+            ..
+        } => {
+            check_team(*team_number as i64, rules, &mut issues);
+
+            if *match_number < 1 {
+                issues.push(Issue::error(
+                    "match_number",
+                    format!("must be at least 1, got {}", match_number),
+                ));
+            }
+
+            if event.is_empty() {
+                issues.push(Issue::error("event", "is required".to_string()));
+            } else if !known_events.is_empty() && !known_events.contains(event) {
+                issues.push(Issue {
+                    field: "event".to_string(),
+                    severity: rules.unknown_event,
+                    message: format!("{} is not a known event", event),
+                });
+            }
+
+            for field in game.match_fields() {
+                let value = match form.value(field.name) {
+                    Some(value) => value,
+                    None => continue,
+                };
+
+                match field.kind {
+                    FieldKind::Count | FieldKind::Score if value < 0 => {
+                        issues.push(Issue::error(
+                            field.name,
+                            format!("can't be negative, got {}", value),
+                        ));
+                    }
+                    FieldKind::Level { min, max } if value < min as i64 || value > max as i64 => {
+                        issues.push(Issue::error(
+                            field.name,
+                            format!("must be between {} and {}, got {}", min, max, value),
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+
+            for limit in game.sum_limits() {
+                let total: i64 = limit
+                    .fields
+                    .iter()
+                    .map(|f| form.value(f).unwrap_or(0))
+                    .sum();
+                let at_most = form.value(limit.at_most).unwrap_or(0);
+
+                if total > at_most {
+                    issues.push(Issue::error(
+                        limit.at_most,
+                        format!(
+                            "is {}, but {} add up to {}",
+                            at_most,
+                            limit.fields.join(" and "),
+                            total
+                        ),
+                    ));
+                }
+            }
+
+            warn_above(form, rules, &mut issues);
+        }
+        FormIngest::Pit {
+            team_number,
+            size,
+            climb,
+            build_quality,
+            driver_team,
+            confidence,
+            ..
+        } => {
+            check_team(*team_number as i64, rules, &mut issues);
+
+            for (name, value) in [("size.x", size.x), ("size.y", size.y), ("size.z", size.z)] {
+                if value < 0.0 || !value.is_finite() {
+                    issues.push(Issue::error(
+                        name,
+                        format!("must be a positive number, got {}", value),
+                    ));
+                }
+            }
+
+            // the pit form's climb uses the same levels as the match form
+            let levels = game.match_fields().iter().find_map(|f| match f.kind {
+                FieldKind::Level { min, max } if f.name == "climb" => Some((min, max)),
+                _ => None,
+            });
+            if let Some((min, max)) = levels {
+                if *climb < min || *climb > max {
+                    issues.push(Issue::error(
+                        "climb",
+                        format!("must be between {} and {}, got {}", min, max, climb),
+                    ));
+                }
+            }
+
+            for (name, value) in [
+                ("build_quality", build_quality),
+                ("driver_team", driver_team),
+                ("confidence", confidence),
+            ] {
+                if *value < 0 {
+                    issues.push(Issue::error(
+                        name,
+                        format!("can't be negative, got {}", value),
+                    ));
+                }
+            }
+
+            warn_above(form, rules, &mut issues);
+        }
+    }
+
+    issues
+}
+
+fn check_team(team_number: i64, rules: &Rules, issues: &mut Vec<Issue>) {
+    if team_number < 1 || team_number > rules.max_team_number as i64 {
+        issues.push(Issue::error(
+            "team_number",
+            format!(
+                "must be between 1 and {}, got {}",
+                rules.max_team_number, team_number
+            ),
+        ));
+    }
+}
+
+fn warn_above(form: &FormIngest, rules: &Rules, issues: &mut Vec<Issue>) {
+    for (field, &max) in &rules.warn_above {
+        match form_value(form, field) {
+            Some(value) if value > max => issues.push(Issue::warning(
+                field,
+                format!("is {}, more than the usual maximum of {}", value, max),
+            )),
+            _ => {}
+        }
+    }
+}
+
+/// Like `FieldValues::value`, but also covers the numeric fields of the pit form.
+fn form_value(form: &FormIngest, field: &str) -> Option<i64> {
+    match form {
+        FormIngest::Match { .. } => form.value(field),
+        FormIngest::Pit {
+            weight,
+            climb,
+            build_quality,
+            driver_team,
+            confidence,
+            ..
+        } => Some(match field {
+            "weight" => *weight as i64,
+            "climb" => *climb as i64,
+            "build_quality" => *build_quality as i64,
+            "driver_team" => *driver_team as i64,
+            "confidence" => *confidence as i64,
+            _ => return None,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        db::MatchResponse,
+        game::Size,
+        schedule::{self, ScheduledMatch},
+        season,
+        testing::{match_response, with_database},
+    };
+
+    fn known() -> Vec<String> {
+        vec!["2022test".to_string()]
+    }
+
+    fn check_match(r: MatchResponse, rules: &Rules) -> Vec<(String, Severity)> {
+        let form = FormIngest::from(r);
+        check(&form, season::current(), rules, &known())
+            .into_iter()
+            .map(|issue| (issue.field, issue.severity))
+            .collect()
+    }
+
+    fn check_pit(form: FormIngest) -> Vec<(String, Severity)> {
+        check(&form, season::current(), &Rules::default(), &[])
+            .into_iter()
+            .map(|issue| (issue.field, issue.severity))
+            .collect()
+    }
+
+    fn error(field: &str) -> (String, Severity) {
+        (field.to_string(), Severity::Error)
+    }
+
+    fn warning(field: &str) -> (String, Severity) {
+        (field.to_string(), Severity::Warning)
+    }
+
+    fn pit_form(size: Size, climb: i8, build_quality: i16, weight: u16) -> FormIngest {
+        FormIngest::Pit {
+            time_stamp: 0,
+            team_name: "Test".to_string(),
+            team_number: 254,
+            drivetrain: "swerve".to_string(),
+            weight,
+            size,
+            can_shoot_auto_upper: true,
+            can_shoot_auto_lower: false,
+            can_shoot_teleop_upper: true,
+            can_shoot_teleop_lower: false,
+            climb,
+            comment: String::new(),
+            build_quality,
+            driver_team: 5,
+            confidence: 5,
+            picture: String::new(),
+            images: Vec::new(),
+            submission_id: None,
+        }
+    }
+
+    fn size() -> Size {
+        Size {
+            x: 30.0,
+            y: 30.0,
+            z: 40.0,
+        }
+    }
+
+    #[test]
+    fn plausible_forms_pass() {
+        let mut r = match_response(1, 254);
+        r.auto_scored_upper = 2;
+        r.auto_shots = 3;
+        r.teleop_scored_lower = 4;
+        r.teleop_scored_upper = 10;
+        r.teleop_shots = 20;
+        r.climb = 3;
+        r.red_score = 80;
+        assert_eq!(check_match(r, &Rules::default()), vec![]);
+
+        assert_eq!(check_pit(pit_form(size(), 2, 7, 110)), vec![]);
+    }
+
+    #[test]
+    fn team_numbers_must_be_in_range() {
+        let rules = Rules::default();
+        assert_eq!(
+            check_match(match_response(1, 0), &rules),
+            vec![error("team_number")]
+        );
+        assert_eq!(
+            check_match(match_response(1, 10000), &rules),
+            vec![error("team_number")]
+        );
+        assert_eq!(check_match(match_response(1, 9999), &rules), vec![]);
+
+        let rules = Rules {
+            max_team_number: 100,
+            ..Rules::default()
+        };
+        assert_eq!(
+            check_match(match_response(1, 254), &rules),
+            vec![error("team_number")]
+        );
+
+        let mut pit = pit_form(size(), 0, 5, 100);
+        if let FormIngest::Pit { team_number, .. } = &mut pit {
+            *team_number = -4;
+        }
+        assert_eq!(check_pit(pit), vec![error("team_number")]);
+    }
+
+    #[test]
+    fn match_numbers_start_at_one() {
+        let rules = Rules::default();
+        assert_eq!(
+            check_match(match_response(0, 254), &rules),
+            vec![error("match_number")]
+        );
+        assert_eq!(
+            check_match(match_response(-3, 254), &rules),
+            vec![error("match_number")]
+        );
+    }
+
+    #[test]
+    fn events_must_be_given_and_known() {
+        let rules = Rules::default();
+
+        let mut r = match_response(1, 254);
+        r.event = String::new();
+        assert_eq!(check_match(r, &rules), vec![error("event")]);
+
+        let mut r = match_response(1, 254);
+        r.event = "2022other".to_string();
+        assert_eq!(check_match(r.clone(), &rules), vec![error("event")]);
+
+        let lenient = Rules {
+            unknown_event: Severity::Warning,
+            ..Rules::default()
+        };
+        assert_eq!(check_match(r.clone(), &lenient), vec![warning("event")]);
+
+        // with no events known yet, any is accepted
+        let form = FormIngest::from(r);
+        assert!(check(&form, season::current(), &rules, &[]).is_empty());
+    }
+
+    #[test]
+    fn counts_and_scores_cannot_be_negative() {
+        let mut r = match_response(1, 254);
+        r.pins = -1;
+        r.penalties = -2;
+        r.blue_score = -5;
+        assert_eq!(
+            check_match(r, &Rules::default()),
+            vec![error("pins"), error("penalties"), error("blue_score")]
+        );
+    }
+
+    #[test]
+    fn climbs_must_be_a_known_level() {
+        let mut r = match_response(1, 254);
+        r.climb = 4;
+        assert_eq!(check_match(r, &Rules::default()), vec![error("climb")]);
+
+        let mut r = match_response(1, 254);
+        r.climb = -2;
+        assert_eq!(check_match(r, &Rules::default()), vec![error("climb")]);
+
+        assert_eq!(check_pit(pit_form(size(), 4, 5, 100)), vec![error("climb")]);
+    }
+
+    #[test]
+    fn cargo_scored_cannot_exceed_shots() {
+        let mut r = match_response(1, 254);
+        r.auto_scored_lower = 1;
+        r.auto_scored_upper = 2;
+        r.auto_shots = 2;
+        r.teleop_scored_upper = 8;
+        r.teleop_shots = 8;
+        assert_eq!(check_match(r, &Rules::default()), vec![error("auto_shots")]);
+    }
+
+    #[test]
+    fn unlikely_values_only_warn() {
+        // six upper from six shots is possible, just unusual
+        let mut r = match_response(1, 254);
+        r.auto_scored_upper = 6;
+        r.auto_shots = 6;
+        assert_eq!(
+            check_match(r.clone(), &Rules::default()),
+            vec![warning("auto_scored_upper")]
+        );
+
+        // seven from six is not, and is still unusual
+        r.auto_scored_upper = 7;
+        assert_eq!(
+            check_match(r, &Rules::default()),
+            vec![error("auto_shots"), warning("auto_scored_upper")]
+        );
+
+        assert_eq!(
+            check_pit(pit_form(size(), 0, 11, 130)),
+            vec![warning("build_quality"), warning("weight"),]
+        );
+    }
+
+    #[test]
+    fn configured_limits_replace_the_defaults() {
+        let rules = Rules {
+            warn_above: [("penalties".to_string(), 1)].into_iter().collect(),
+            ..Rules::default()
+        };
+
+        let mut r = match_response(1, 254);
+        r.penalties = 2;
+        r.auto_scored_upper = 6;
+        r.auto_shots = 6;
+        assert_eq!(check_match(r, &rules), vec![warning("penalties")]);
+    }
+
+    #[test]
+    fn pit_sizes_must_be_positive_numbers() {
+        let bad = Size {
+            x: -1.0,
+            y: f32::NAN,
+            z: f32::INFINITY,
+        };
+        assert_eq!(
+            check_pit(pit_form(bad, 0, 5, 100)),
+            vec![error("size.x"), error("size.y"), error("size.z"),]
+        );
+    }
+
+    #[test]
+    fn pit_ratings_cannot_be_negative() {
+        assert_eq!(
+            check_pit(pit_form(size(), 0, -1, 100)),
+            vec![error("build_quality")]
+        );
+    }
+
+    #[test]
+    fn known_events_combine_schedule_active_and_configured() {
+        with_database(|mut conn| async move {
+            let scheduled = ScheduledMatch {
+                match_number: 1,
+                red: vec![1, 2, 3],
+                blue: vec![4, 5, 6],
+            };
+            schedule::store(&mut conn, "2022sched", &[scheduled])
+                .await
+                .unwrap();
+            let rules = Rules {
+                events: vec!["2022extra".to_string()],
+                ..Rules::default()
+            };
+
+            let mut events = known_events(&mut conn, Some("2022active"), &rules)
+                .await
+                .unwrap();
+            events.sort();
+            assert_eq!(events, vec!["2022active", "2022extra", "2022sched"]);
+        })
+    }
+}